@@ -0,0 +1,187 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+use batch_rename::{filter, ConflictPolicy, ExtCase, GroupBy, HashSpec, NumberPosition, SortOrder, SplitFrom, StemCase,
+                   Template, UnicodeForm};
+
+use crate::{Rule, Separators, SwapIndices};
+
+/// The config file looked up in the target directory unless `--no-config` is given.
+pub const CONFIG_NAME: &str = ".batch_renamer.toml";
+
+/**
+ * Default options loaded from a TOML file. The keys are named after the
+ * command line flags, with underscores instead of dashes, e.g.
+ * `separator = " - "` or `number_width = 3`. Flags given on the command
+ * line win over the config.
+ */
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub canonicalize: Option<bool>,
+    pub extensions: Option<Vec<String>>,
+    pub include_no_ext: Option<bool>,
+    pub case_sensitive_ext: Option<bool>,
+    pub new_extension: Option<String>,
+    pub ext_case: Option<ExtCase>,
+    pub lowercase_ext: Option<bool>,
+    #[serde(default, deserialize_with = "parse")]
+    pub separator: Option<Separators>,
+    #[serde(default, deserialize_with = "parse")]
+    pub separator_regex: Option<regex::Regex>,
+    pub split_from: Option<SplitFrom>,
+    #[serde(default, deserialize_with = "parse_all")]
+    pub rules: Option<Vec<Rule>>,
+    pub order: Option<Vec<usize>>,
+    #[serde(default, deserialize_with = "parse")]
+    pub swap_indices: Option<SwapIndices>,
+    pub min_parts: Option<usize>,
+    pub max_parts: Option<usize>,
+    pub padding: Option<String>,
+    pub recursive: Option<bool>,
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: Option<bool>,
+    pub include_dirs: Option<bool>,
+    pub target_dir: Option<PathBuf>,
+    pub backup: Option<PathBuf>,
+    pub create_dirs: Option<bool>,
+    pub verify: Option<bool>,
+    pub preserve_timestamps: Option<bool>,
+    pub mirror_dirs: Option<bool>,
+    pub flatten: Option<bool>,
+    pub sort: Option<SortOrder>,
+    pub reverse_sort: Option<bool>,
+    pub limit: Option<usize>,
+    pub skip_hidden: Option<bool>,
+    pub on_conflict: Option<ConflictPolicy>,
+    pub conflict_suffix: Option<String>,
+    pub jobs: Option<u64>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u64>,
+    #[serde(default, deserialize_with = "parse_all")]
+    pub include: Option<Vec<glob::Pattern>>,
+    #[serde(default, deserialize_with = "parse_all")]
+    pub exclude: Option<Vec<glob::Pattern>>,
+    #[serde(default, deserialize_with = "parse_size")]
+    pub min_size: Option<u64>,
+    #[serde(default, deserialize_with = "parse_size")]
+    pub max_size: Option<u64>,
+    #[serde(default, deserialize_with = "parse_duration")]
+    pub newer_than: Option<Duration>,
+    #[serde(default, deserialize_with = "parse_duration")]
+    pub older_than: Option<Duration>,
+    pub state: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parse")]
+    pub regex: Option<regex::Regex>,
+    pub replace: Option<String>,
+    #[serde(default, deserialize_with = "parse")]
+    pub template: Option<Template>,
+    #[serde(default, deserialize_with = "parse")]
+    pub fallback_template: Option<Template>,
+    pub exec: Option<String>,
+    pub exec_timeout: Option<u64>,
+    #[cfg(feature = "id3")]
+    #[serde(default, deserialize_with = "parse")]
+    pub from_id3: Option<Template>,
+    #[cfg(feature = "exif")]
+    pub from_exif: Option<String>,
+    pub strip_chars: Option<String>,
+    pub find: Option<Vec<String>>,
+    pub replace_with: Option<Vec<String>>,
+    pub squeeze_spaces: Option<bool>,
+    pub normalize_separators: Option<char>,
+    pub separator_chars: Option<String>,
+    pub case: Option<StemCase>,
+    pub insert: Option<String>,
+    pub at: Option<isize>,
+    #[serde(default, deserialize_with = "parse")]
+    pub append_hash: Option<HashSpec>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub sanitize: Option<bool>,
+    pub sanitize_replacement: Option<String>,
+    pub ascii_fold: Option<bool>,
+    pub slugify: Option<bool>,
+    pub normalize: Option<UnicodeForm>,
+    pub number: Option<bool>,
+    pub number_start: Option<u64>,
+    pub number_width: Option<usize>,
+    pub number_position: Option<NumberPosition>,
+    #[serde(default, deserialize_with = "parse")]
+    pub group_by: Option<GroupBy>,
+    pub renumber: Option<bool>,
+    #[serde(default, deserialize_with = "parse")]
+    pub renumber_regex: Option<regex::Regex>,
+    pub unnumbered_last: Option<bool>,
+    pub max_name_len: Option<usize>,
+}
+
+impl Config {
+    /**
+     * Reads and parses the config file.
+     *
+     * @return The config, or a message saying why it cannot be used.
+     */
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config `{}`: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid config `{}`: {}", path.display(), e))
+    }
+}
+
+/**
+ * Parses a string value the same way the command line flag is parsed.
+ */
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| text.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+/**
+ * Parses a list of string values the same way the repeated flag is parsed.
+ */
+fn parse_all<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|texts| texts.iter().map(|text| text.parse().map_err(de::Error::custom)).collect())
+        .transpose()
+}
+
+/**
+ * Parses a duration like `2d` or a date like `2024-01-01` the same way `--newer-than` does.
+ */
+fn parse_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| filter::parse_age(&text).map_err(de::Error::custom))
+        .transpose()
+}
+
+/**
+ * Parses a size like `1K` the same way `--min-size` does.
+ */
+fn parse_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| filter::parse_size(&text).map_err(de::Error::custom))
+        .transpose()
+}
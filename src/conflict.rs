@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+use crate::{filter, RenameError, RenameOptions, Result};
+
+/**
+ * What to do when a rename would replace another file.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Leave the conflicting files untouched.
+    #[default]
+    Skip,
+    /// Stop before renaming anything.
+    Abort,
+    /// Rename anyway, replacing the existing file.
+    Overwrite,
+    /// Rename to the new name with a numbered suffix before the extension,
+    /// e.g. `a (1).txt`, counting up until the name is free.
+    Rename,
+}
+
+/**
+ * Several files that would end up with the same name, or a file whose new
+ * name is already taken by a file that is not renamed away.
+ */
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// The files that map to `target`.
+    pub sources: Vec<PathBuf>,
+    /// The contested new path.
+    pub target: PathBuf,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let [source] = self.sources.as_slice() {
+            return write!(f, "`{}` maps to existing `{}`", source.display(), self.target.display());
+        }
+        let sources = self.sources.iter()
+            .map(|p| format!("`{}`", p.display()))
+            .collect::<Vec<_>>();
+        let quantifier = if sources.len() == 2 { "both" } else { "all" };
+        write!(f, "{} {} map to `{}`", sources.join(" and "), quantifier, self.target.display())
+    }
+}
+
+/**
+ * Finds the renames that would clobber another file and applies the conflict
+ * policy to them. With `Skip`, every rename involved in a conflict is removed
+ * from `ops`; with `Overwrite`, `ops` is left as is; with `Rename`, the first of
+ * several files mapping to a free name keeps it and the others get a suffix.
+ *
+ * @param ops The planned `(old, new)` renames.
+ * @param options The conflict policy, the suffix `Rename` adds, its `{n}`
+ *        replaced by the first number giving a free name, and the extensions
+ *        it goes before.
+ *
+ * @return The conflicts found.
+ * @throws RenameError::Conflict if a conflict is found and the policy is `Abort`.
+ * @throws RenameError::InvalidSuffix if the policy is `Rename` and the suffix has no `{n}`.
+ */
+pub fn resolve_conflicts(ops: &mut Vec<(PathBuf, PathBuf)>, options: &RenameOptions) -> Result<Vec<Conflict>> {
+    let suffix = options.conflict_suffix.as_str();
+    let sources = ops.iter().map(|(old, _)| old.clone()).collect::<HashSet<_>>();
+    let mut by_target: HashMap<&PathBuf, Vec<&PathBuf>> = HashMap::new();
+    for (old, new) in ops.iter() {
+        by_target.entry(new).or_default().push(old);
+    }
+
+    let mut conflicts = Vec::new();
+    for (old, new) in ops.iter() {
+        let claimants = &by_target[new];
+        if claimants.len() > 1 {
+            // report each shared target once, from its first claimant
+            if claimants[0] == old {
+                conflicts.push(Conflict {
+                    sources: claimants.iter().map(|p| (*p).clone()).collect(),
+                    target: new.clone(),
+                });
+            }
+        } else if old != new && new.exists() && !sources.contains(new) {
+            conflicts.push(Conflict { sources: vec![old.clone()], target: new.clone() });
+        }
+    }
+
+    match options.on_conflict {
+        ConflictPolicy::Abort => {
+            if let Some(conflict) = conflicts.first() {
+                return Err(RenameError::Conflict(conflict.clone()));
+            }
+        }
+        ConflictPolicy::Skip => {
+            let contested = conflicts.iter().map(|c| &c.target).collect::<HashSet<_>>();
+            ops.retain(|(_, new)| !contested.contains(new));
+        }
+        ConflictPolicy::Overwrite => {}
+        ConflictPolicy::Rename => {
+            if !suffix.contains("{n}") {
+                return Err(RenameError::InvalidSuffix(suffix.to_string()));
+            }
+            let mut moved = HashSet::new();
+            for conflict in &conflicts {
+                // a name no other file has goes to the first file mapping to it
+                let occupied = conflict.target.exists() && !sources.contains(&conflict.target);
+                moved.extend(conflict.sources.iter().skip(if occupied { 0 } else { 1 }).cloned());
+            }
+            let mut taken = ops.iter().map(|(_, new)| new.clone()).collect::<HashSet<_>>();
+            for (_, new) in ops.iter_mut().filter(|(old, _)| moved.contains(old)) {
+                let free = (1..)
+                    .map(|n| with_suffix(new, suffix, n, options))
+                    .find(|candidate| !taken.contains(candidate) && !candidate.exists())
+                    .expect("Ran out of suffix numbers");
+                taken.insert(free.clone());
+                *new = free;
+            }
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/**
+ * The path with the suffix, its `{n}` replaced by `n`, added to the file name
+ * before the extension, split off as when planning, e.g. `a (1).tar.gz`. A
+ * name without one of the extensions, e.g. given in a CSV, has its suffix put
+ * before whatever follows its last dot.
+ */
+fn with_suffix(path: &Path, suffix: &str, n: u64, options: &RenameOptions) -> PathBuf {
+    let suffix = suffix.replace("{n}", &n.to_string());
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        return path.with_file_name(name);
+    };
+    let extensions = options.extensions.iter()
+        .cloned()
+        .chain(options.new_extension.iter().map(|extension| extension.trim_start_matches('.').to_string()))
+        .filter(|extension| !extension.is_empty())
+        .collect::<Vec<_>>();
+    let name = match filter::split_extension(name, &extensions, options.case_sensitive_ext)
+        .or_else(|| filter::split_last_extension(name)) {
+        Some((stem, extension)) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", name, suffix),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn options(extensions: &[&str]) -> RenameOptions {
+        RenameOptions {
+            extensions: extensions.iter().map(|extension| extension.to_string()).collect(),
+            ..RenameOptions::default()
+        }
+    }
+
+    /**
+     * Two files mapping to the same name, a file renamed onto a file that
+     * stays, and one that is renamed freely, in a new directory.
+     */
+    fn conflicting_ops(name: &str) -> (PathBuf, Vec<(PathBuf, PathBuf)>) {
+        let directory = std::env::temp_dir().join(format!("batch_rename_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("kept.mp3"), "").unwrap();
+        let ops = [("a.mp3", "same.mp3"), ("b.mp3", "same.mp3"), ("c.mp3", "kept.mp3"), ("d.mp3", "free.mp3")]
+            .iter()
+            .map(|(old, new)| (directory.join(old), directory.join(new)))
+            .collect();
+        (directory, ops)
+    }
+
+    fn with_policy(on_conflict: ConflictPolicy) -> RenameOptions {
+        RenameOptions { on_conflict, ..options(&["mp3"]) }
+    }
+
+    #[test]
+    fn suffix_goes_before_the_whole_extension() {
+        let options = options(&["gz", "tar.gz"]);
+        assert_eq!(with_suffix(Path::new("d/archive.tar.gz"), " ({n})", 1, &options), Path::new("d/archive (1).tar.gz"));
+        assert_eq!(with_suffix(Path::new(".env"), "_{n}", 2, &options), Path::new(".env_2"));
+        assert_eq!(with_suffix(Path::new("notes.txt"), "_{n}", 3, &options), Path::new("notes_3.txt"));
+    }
+
+    #[test]
+    fn finds_shared_and_occupied_targets() {
+        let (directory, mut ops) = conflicting_ops("conflicts_found");
+        let conflicts = resolve_conflicts(&mut ops, &with_policy(ConflictPolicy::Overwrite)).unwrap();
+        let conflicts = conflicts.into_iter().map(|conflict| (conflict.sources, conflict.target)).collect::<Vec<_>>();
+        assert_eq!(conflicts, [
+            (vec![directory.join("a.mp3"), directory.join("b.mp3")], directory.join("same.mp3")),
+            (vec![directory.join("c.mp3")], directory.join("kept.mp3")),
+        ]);
+        assert_eq!(ops.len(), 4);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn skip_drops_every_conflicting_rename() {
+        let (directory, mut ops) = conflicting_ops("conflicts_skipped");
+        resolve_conflicts(&mut ops, &with_policy(ConflictPolicy::Skip)).unwrap();
+        assert_eq!(ops, vec![(directory.join("d.mp3"), directory.join("free.mp3"))]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn abort_fails_on_the_first_conflict() {
+        let (directory, mut ops) = conflicting_ops("conflicts_aborted");
+        let result = resolve_conflicts(&mut ops, &with_policy(ConflictPolicy::Abort));
+        assert!(matches!(result, Err(RenameError::Conflict(conflict)) if conflict.target == directory.join("same.mp3")));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rename_moves_all_but_the_first_to_free_names() {
+        let (directory, mut ops) = conflicting_ops("conflicts_renamed");
+        fs::write(directory.join("kept_1.mp3"), "").unwrap();
+        let options = RenameOptions { conflict_suffix: "_{n}".to_string(), ..with_policy(ConflictPolicy::Rename) };
+        resolve_conflicts(&mut ops, &options).unwrap();
+        let new_names = ops.iter().map(|(_, new)| new.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(new_names, ["same.mp3", "same_1.mp3", "kept_2.mp3", "free.mp3"]);
+
+        let options = RenameOptions { conflict_suffix: "_copy".to_string(), ..options };
+        let (_, mut ops) = conflicting_ops("conflicts_renamed");
+        assert!(matches!(resolve_conflicts(&mut ops, &options), Err(RenameError::InvalidSuffix(_))));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::conflict::Conflict;
+
+/**
+ * Everything that can go wrong while renaming files.
+ */
+#[derive(Debug)]
+pub enum RenameError {
+    /// An underlying filesystem operation failed.
+    Io(io::Error),
+    /// A directory could not be listed, e.g. for lack of permission.
+    CannotRead(PathBuf, io::Error),
+    /// The given separator cannot be used to split file names.
+    InvalidSeparator(String),
+    /// The part order is not a permutation of the part indices.
+    InvalidOrder(Vec<usize>),
+    /// The template is malformed, e.g. has an unclosed `{`.
+    InvalidTemplate(String),
+    /// The template uses a token that cannot be filled in.
+    UnknownToken(String),
+    /// The date format uses an unknown specifier.
+    InvalidDateFormat(String),
+    /// The duration is not a number followed by a known unit, nor a date or phrase saying when.
+    InvalidDuration(String),
+    /// The size is not a number optionally followed by a known unit.
+    InvalidSize(String),
+    /// The hash is not a known algorithm optionally followed by `:` and a length.
+    InvalidHash(String),
+    /// The grouping is neither `subdir` nor `regex:` followed by a valid regex.
+    InvalidGroup(String),
+    /// The conflict suffix has no `{n}` to number the new names with.
+    InvalidSuffix(String),
+    /// The rename failed, and the file could not be moved back from the
+    /// temporary name it was parked at, where it is left.
+    Stranded(PathBuf, Box<RenameError>),
+    /// The rename target is already taken by another file.
+    TargetExists(PathBuf),
+    /// The path cannot be represented as UTF-8.
+    NonUtf8Path(PathBuf),
+    /// The path does not exist.
+    NotFound(PathBuf),
+    /// The path should be a directory but is not.
+    NotADirectory(PathBuf),
+    /// A rename would replace another file.
+    Conflict(Conflict),
+    /// An earlier run was interrupted and left its journal, which must be resumed or removed first.
+    Interrupted(PathBuf),
+    /// The file is not a journal of renames.
+    InvalidJournal(PathBuf),
+    /// The journal of renames at the path could not be written.
+    Journal(PathBuf, serde_json::Error),
+    /// The undo log could not be parsed or written.
+    UndoLog(serde_json::Error),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenameError::Io(e) => write!(f, "{}", e),
+            RenameError::CannotRead(path, e) => write!(f, "Cannot read `{}`: {}", path.display(), e),
+            RenameError::InvalidSeparator(sep) if sep.is_empty() => {
+                write!(f, "Invalid separator ``, the separator to split on cannot be empty; write `\\,` for a comma")
+            }
+            RenameError::InvalidSeparator(sep) => write!(f, "Invalid separator `{}`", sep),
+            RenameError::InvalidOrder(order) => {
+                let order = order.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+                write!(f, "Invalid part order `{}`, every index from 0 must appear exactly once", order.join(","))
+            }
+            RenameError::InvalidTemplate(template) => write!(f, "Invalid template `{}`", template),
+            RenameError::UnknownToken(token) => write!(f, "Unknown template token `{{{}}}`", token),
+            RenameError::InvalidDateFormat(format) => write!(f, "Invalid date format `{}`", format),
+            RenameError::InvalidDuration(duration) => {
+                write!(f, "Invalid duration `{}`, expected a number and a unit like `3h` or `2d`, a date like `2024-01-01`, \
+                           or a phrase like `yesterday`, `last monday` or `3 days ago`", duration)
+            }
+            RenameError::InvalidSize(size) => {
+                write!(f, "Invalid size `{}`, expected a number of bytes like `500`, `1K` or `2M`", size)
+            }
+            RenameError::InvalidHash(hash) => {
+                write!(f, "Invalid hash `{}`, expected `crc32` or `sha256`, optionally followed by a length like `:8`", hash)
+            }
+            RenameError::InvalidGroup(group) => {
+                write!(f, "Invalid grouping `{}`, expected `subdir` or `regex:` followed by a regex", group)
+            }
+            RenameError::InvalidSuffix(suffix) => {
+                write!(f, "Invalid conflict suffix `{}`, it must contain `{{n}}` to number the names with", suffix)
+            }
+            RenameError::Stranded(path, error) => {
+                write!(f, "{}, and the file could not be moved back, so it is left at `{}`", error, path.display())
+            }
+            RenameError::TargetExists(path) => write!(f, "`{}` already exists", path.display()),
+            RenameError::NonUtf8Path(path) => write!(f, "`{}` is not a valid UTF-8 path", path.display()),
+            RenameError::NotFound(path) => write!(f, "`{}` does not exist", path.display()),
+            RenameError::NotADirectory(path) => write!(f, "`{}` is not a directory", path.display()),
+            RenameError::Conflict(conflict) => write!(f, "Conflict: {}", conflict),
+            RenameError::Interrupted(path) => {
+                write!(f, "An earlier run was interrupted, resume it from `{}` or remove that file", path.display())
+            }
+            RenameError::InvalidJournal(path) => write!(f, "`{}` is not a rename journal", path.display()),
+            RenameError::Journal(path, e) => write!(f, "Cannot write the rename journal `{}`: {}", path.display(), e),
+            RenameError::UndoLog(e) => write!(f, "Invalid undo log: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenameError::Io(e) | RenameError::CannotRead(_, e) => Some(e),
+            RenameError::UndoLog(e) | RenameError::Journal(_, e) => Some(e),
+            RenameError::Stranded(_, error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RenameError {
+    fn from(e: io::Error) -> Self {
+        RenameError::Io(e)
+    }
+}
+
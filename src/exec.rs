@@ -0,0 +1,108 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::SkipReason;
+
+/// How often to check whether the command is done.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/**
+ * Runs the command through the shell with the stem on stdin, and as `$1` where
+ * the shell has one, and reads the new stem from what it prints, without the
+ * line break at the end.
+ *
+ * @param command The shell command, e.g. `tr a-z A-Z`.
+ * @param stem The stem to transform.
+ * @param timeout How long to let the command run before killing it.
+ *
+ * @return The new stem, or why the file should be skipped: the command could
+ *         not be run, timed out, failed, or printed no name or more than one line.
+ */
+pub fn run_command(command: &str, stem: &str, timeout: Duration) -> Result<String, SkipReason> {
+    let failed = |message: String| SkipReason::CommandFailed(message);
+    let mut child = shell(command, stem)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("cannot run `{}`: {}", command, e)))?;
+
+    // read and written on their own threads so a chatty command cannot block on a full pipe
+    let stdin = child.stdin.take();
+    let stem = stem.to_string();
+    let writer = thread::spawn(move || {
+        // a command that does not read its input is fine
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(stem.as_bytes());
+        }
+    });
+    let stdout = child.stdout.take();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut stdout) = stdout {
+            let _ = stdout.read_to_end(&mut output);
+        }
+        output
+    });
+    let stderr = child.stderr.take();
+    let error_reader = thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut output);
+        }
+        output
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(failed(format!("`{}` took longer than {:?}", command, timeout)));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(failed(format!("cannot wait for `{}`: {}", command, e))),
+        }
+    };
+    let _ = writer.join();
+    let output = reader.join().unwrap_or_default();
+    let errors = error_reader.join().unwrap_or_default();
+
+    if !status.success() {
+        let mut message = format!("`{}` failed with {}", command, status);
+        if let Some(line) = errors.lines().find(|line| !line.trim().is_empty()) {
+            message.push_str(": ");
+            message.push_str(line.trim());
+        }
+        return Err(failed(message));
+    }
+    let output = String::from_utf8(output).map_err(|_| failed(format!("`{}` printed a name that is not UTF-8", command)))?;
+    let new_stem = output.strip_suffix('\n').unwrap_or(&output);
+    let new_stem = new_stem.strip_suffix('\r').unwrap_or(new_stem);
+    if new_stem.is_empty() {
+        return Err(failed(format!("`{}` printed no name", command)));
+    }
+    if new_stem.contains(['\n', '\r']) {
+        return Err(failed(format!("`{}` printed more than one line", command)));
+    }
+    Ok(new_stem.to_string())
+}
+
+#[cfg(unix)]
+fn shell(command: &str, stem: &str) -> Command {
+    let mut shell = Command::new("sh");
+    // the word after the script is `$0`, the stem after it `$1`
+    shell.arg("-c").arg(command).arg("batch_renamer").arg(stem);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str, _stem: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
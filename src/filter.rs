@@ -0,0 +1,293 @@
+use std::path::Path;
+use std::time::Duration;
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, Weekday};
+use glob::{MatchOptions, Pattern};
+
+use crate::journal::{JOURNAL_NAME, TEMP_NAME_PREFIX};
+use crate::undo::UNDO_LOG_NAME;
+use crate::{RenameError, Result};
+
+/// The file listing, like a `.gitignore`, the files in its directory and below to leave alone.
+pub const IGNORE_FILE_NAME: &str = ".renamerignore";
+
+/// Windows file names are case-insensitive, so patterns should be too.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: !cfg!(windows),
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/**
+ * Returns whether the file name matches any of the given glob patterns.
+ */
+pub fn matches_any(file_name: &str, patterns: &[Pattern]) -> bool {
+    patterns.iter().any(|p| p.matches_with(file_name, MATCH_OPTIONS))
+}
+
+/**
+ * Returns whether the file is one the tool keeps next to the files it renames:
+ * the undo log, the journal of a run, or a file moved to a temporary name by it.
+ */
+pub fn is_own_file(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+        name == UNDO_LOG_NAME || name == JOURNAL_NAME || name.starts_with(TEMP_NAME_PREFIX)
+    })
+}
+
+/**
+ * Splits the file name into its stem and the longest of the given extensions it
+ * ends with, ignoring case unless told otherwise. Extensions may span several
+ * dots, e.g. `tar.gz`, and the stem must not be empty, so `.mp3` has no
+ * extension just like for `Path::extension`.
+ *
+ * @return The stem and the extension as spelled in the file name, or `None` if no extension matches.
+ */
+pub fn split_extension<'a>(file_name: &'a str, extensions: &[String],
+                           case_sensitive: bool) -> Option<(&'a str, &'a str)> {
+    extensions.iter()
+        .filter_map(|extension| {
+            let stem_len = file_name.len().checked_sub(extension.len() + 1)?;
+            let stem = file_name.get(..stem_len).filter(|stem| !stem.is_empty())?;
+            let suffix = file_name[stem_len..].strip_prefix('.')?;
+            let matches = if case_sensitive {
+                suffix == extension
+            } else {
+                suffix.to_lowercase() == extension.to_lowercase()
+            };
+            matches.then_some((stem, suffix))
+        })
+        .max_by_key(|(_, suffix)| suffix.len())
+}
+
+/**
+ * Splits the file name into its stem and whatever extension follows its last
+ * dot, as for `Path::extension`, except that an empty extension is none.
+ *
+ * @return The stem and the extension, or `None` if the file name has no extension.
+ */
+pub fn split_last_extension(file_name: &str) -> Option<(&str, &str)> {
+    file_name.rsplit_once('.').filter(|(stem, extension)| !stem.is_empty() && !extension.is_empty())
+}
+
+/**
+ * Splits the dot off the start of the stem of a dotfile, e.g. `.env`, so that
+ * renaming neither moves nor drops it and the file stays hidden. A dotfile has
+ * no separate extension unless one of the given extensions follows the stem.
+ *
+ * @return The dot, or an empty string for other files, and the rest of the stem.
+ */
+pub fn split_leading_dot(stem: &str) -> (&str, &str) {
+    match stem.strip_prefix('.') {
+        Some(rest) if !rest.is_empty() => stem.split_at(1),
+        _ => ("", stem),
+    }
+}
+
+/**
+ * Returns whether the entry is hidden, i.e. its name starts with a dot or, on
+ * Windows, it has the hidden attribute.
+ */
+pub fn is_hidden(path: &Path) -> bool {
+    let dotfile = path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+    dotfile || has_hidden_attribute(path)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    std::fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute(_path: &Path) -> bool {
+    false
+}
+
+/**
+ * Parses a duration given as a number followed by a unit, `s`, `m`, `h`, `d`
+ * or `w`, e.g. `90s` or `2d`.
+ *
+ * @throws RenameError::InvalidDuration if the number or unit is missing or unknown.
+ */
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || RenameError::InvalidDuration(text.to_string());
+    let split = text.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
+/**
+ * Parses how long ago a file may have been modified at the most or least: a
+ * duration like `2d`, a date and time like `2024-01-01` or `2024-01-01T12:30`,
+ * which is taken as local time unless it has an offset, or a phrase like
+ * `now`, `today`, `yesterday`, `last monday` or `3 days ago`. Dates are taken
+ * at midnight, and times in the future are no time ago.
+ *
+ * @return How long ago from now the given time is.
+ * @throws RenameError::InvalidDuration if the text is none of these.
+ */
+pub fn parse_age(text: &str) -> Result<Duration> {
+    if let Ok(duration) = parse_duration(text) {
+        return Ok(duration);
+    }
+    let now = Local::now();
+    let time = parse_time(text.trim(), now).ok_or_else(|| RenameError::InvalidDuration(text.to_string()))?;
+    Ok((now - time).to_std().unwrap_or_default())
+}
+
+/**
+ * Parses an absolute date or time, or one relative to `now`, for `parse_age`.
+ */
+fn parse_time(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0)?.and_local_timezone(Local).earliest();
+    let today = now.date_naive();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Local));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return time.and_local_timezone(Local).earliest();
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return midnight(date);
+    }
+
+    let text = text.to_lowercase();
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["now"] => Some(now),
+        ["today"] => midnight(today),
+        ["yesterday"] => midnight(today.pred_opt()?),
+        ["last", weekday] => {
+            let weekday = weekday.parse::<Weekday>().ok()?;
+            // a week ago on the same weekday, as today does not count
+            let days_back = (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+            midnight(today - chrono::Days::new(days_back.into()))
+        }
+        [number, unit, "ago"] => {
+            let number = number.parse::<u64>().ok()?;
+            let seconds = match unit.trim_end_matches('s') {
+                "second" => 1,
+                "minute" => 60,
+                "hour" => 60 * 60,
+                "day" => 24 * 60 * 60,
+                "week" => 7 * 24 * 60 * 60,
+                _ => return None,
+            };
+            let ago = chrono::Duration::from_std(Duration::from_secs(number.checked_mul(seconds)?)).ok()?;
+            now.checked_sub_signed(ago)
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Parses a size in bytes with an optional binary unit, `K`, `M`, `G` or `T`,
+ * possibly followed by `B`, e.g. `500`, `1K` or `2MB`.
+ *
+ * @throws RenameError::InvalidSize if the number or unit is missing or unknown.
+ */
+pub fn parse_size(text: &str) -> Result<u64> {
+    let invalid = || RenameError::InvalidSize(text.to_string());
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+    let unit = unit.to_ascii_uppercase();
+    let power = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(1024u64.pow(power)).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn extensions(extensions: &[&str]) -> Vec<String> {
+        extensions.iter().map(|extension| extension.to_string()).collect()
+    }
+
+    #[test]
+    fn dotfiles_have_no_extension() {
+        assert_eq!(split_extension(".gitignore", &extensions(&["gitignore"]), false), None);
+        assert_eq!(split_extension(".env", &extensions(&["env"]), false), None);
+        assert_eq!(split_leading_dot(".gitignore"), (".", "gitignore"));
+        assert_eq!(split_leading_dot(".env"), (".", "env"));
+    }
+
+    #[test]
+    fn longest_extension_wins() {
+        let extensions = extensions(&["gz", "tar.gz"]);
+        assert_eq!(split_extension("archive.tar.gz", &extensions, false), Some(("archive", "tar.gz")));
+        assert_eq!(split_leading_dot("archive"), ("", "archive"));
+    }
+
+    #[test]
+    fn any_extension_is_the_last() {
+        assert_eq!(split_last_extension("archive.tar.GZ"), Some(("archive.tar", "GZ")));
+        assert_eq!(split_last_extension(".env"), None);
+        assert_eq!(split_last_extension("notes."), None);
+    }
+
+    #[test]
+    fn sizes_take_binary_units() {
+        assert_eq!(parse_size("500").unwrap(), 500);
+        assert_eq!(parse_size("1K").unwrap(), 1024);
+        assert_eq!(parse_size("2mb").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("3GB").unwrap(), 3 * 1024 * 1024 * 1024);
+        for invalid in ["", "K", "1X", "1.5M", "-1", "99999999999T"] {
+            assert!(matches!(parse_size(invalid), Err(RenameError::InvalidSize(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ages_take_durations() {
+        assert_eq!(parse_age("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("2d").unwrap(), Duration::from_secs(2 * 24 * 60 * 60));
+        for invalid in ["", "2", "2y", "soon", "3 days", "last someday"] {
+            assert!(matches!(parse_age(invalid), Err(RenameError::InvalidDuration(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ages_take_times_relative_to_now() {
+        // a Wednesday
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 18, 30, 0).unwrap();
+        let midnight = |day| Local.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap();
+        assert_eq!(parse_time("now", now), Some(now));
+        assert_eq!(parse_time("today", now), Some(midnight(15)));
+        assert_eq!(parse_time("Yesterday", now), Some(midnight(14)));
+        assert_eq!(parse_time("last monday", now), Some(midnight(13)));
+        assert_eq!(parse_time("last wednesday", now), Some(midnight(8)));
+        assert_eq!(parse_time("3 hours ago", now), Some(now - chrono::Duration::hours(3)));
+        assert_eq!(parse_time("1 week ago", now), Some(now - chrono::Duration::weeks(1)));
+        assert_eq!(parse_time("2024-05-01", now), Some(midnight(1)));
+        assert_eq!(parse_time("2024-05-01 12:30", now), Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).earliest());
+    }
+
+    #[test]
+    fn future_times_are_no_time_ago() {
+        assert_eq!(parse_age("2999-01-01").unwrap(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use sha2::{Digest, Sha256};
+
+use crate::error::RenameError;
+
+/**
+ * The digest to compute over the contents of a file.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// CRC-32, 8 hex digits.
+    Crc32,
+    /// SHA-256, 64 hex digits.
+    Sha256,
+}
+
+/**
+ * A digest and how many of its hex digits to keep, given as `crc32` or
+ * `sha256:8`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashSpec {
+    pub algorithm: HashAlgorithm,
+    /// How many leading hex digits to keep, or `None` for all of them.
+    pub len: Option<usize>,
+}
+
+impl FromStr for HashSpec {
+    type Err = RenameError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || RenameError::InvalidHash(text.to_string());
+        let (name, len) = match text.split_once(':') {
+            Some((name, len)) => (name, Some(len.parse::<usize>().ok().filter(|&len| len > 0).ok_or_else(invalid)?)),
+            None => (text, None),
+        };
+        let algorithm = match name.to_lowercase().as_str() {
+            "crc32" => HashAlgorithm::Crc32,
+            "sha256" => HashAlgorithm::Sha256,
+            _ => return Err(invalid()),
+        };
+        Ok(HashSpec { algorithm, len })
+    }
+}
+
+/**
+ * Computes the digest of the file's contents, reading it a chunk at a time.
+ *
+ * @return The digest as lowercase hex, cut to the length asked for.
+ * @throws io::Error if the file cannot be read.
+ */
+pub fn digest(path: &Path, spec: &HashSpec) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut crc32 = crc32fast::Hasher::new();
+    let mut sha256 = Sha256::new();
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        match spec.algorithm {
+            HashAlgorithm::Crc32 => crc32.update(&buffer[..read]),
+            HashAlgorithm::Sha256 => sha256.update(&buffer[..read]),
+        }
+    }
+
+    let mut hex = match spec.algorithm {
+        HashAlgorithm::Crc32 => format!("{:08x}", crc32.finalize()),
+        HashAlgorithm::Sha256 => sha256.finalize().iter().map(|byte| format!("{:02x}", byte)).collect(),
+    };
+    if let Some(len) = spec.len {
+        hex.truncate(len);
+    }
+    Ok(hex)
+}
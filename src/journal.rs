@@ -0,0 +1,176 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::RenameError;
+use crate::{Pending, Result};
+
+/// The name of the journal kept in the renamed directory while renames are performed.
+pub const JOURNAL_NAME: &str = ".batch_renamer_journal.jsonl";
+
+/// How the temporary names start that files are moved to while renaming, e.g. in a swap.
+pub const TEMP_NAME_PREFIX: &str = ".batch_renamer_tmp_";
+
+/// The `(old, new)` paths of a rename that was performed.
+type Performed = (PathBuf, PathBuf);
+
+/// The old path of a rename that cannot be resumed, and why.
+type Lost = (PathBuf, RenameError);
+
+/// A reopened journal with the renames left to perform, performed and given up on.
+type Resumed = (Journal, Vec<Pending>, Vec<Performed>, Vec<Lost>);
+
+/**
+ * A line of the journal. The planned renames come first, numbered by their
+ * position, followed by what happened to them as it happened.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Entry {
+    /// A rename to perform, with absolute paths.
+    Planned { from: PathBuf, to: PathBuf },
+    /// The file of a rename was moved to a temporary name out of the way of another.
+    Parked { op: usize, at: PathBuf },
+    /// The rename was performed.
+    Done { op: usize },
+}
+
+/**
+ * A record of the renames of a run, synced to disk after every step, so that a
+ * run that was killed can be finished with `resume_renames`. It is removed once
+ * the run is over.
+ */
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /**
+     * Starts the journal of the given renames.
+     *
+     * @throws RenameError::Interrupted if there is a journal left by an earlier run.
+     */
+    pub(crate) fn create(path: &Path, ops: &[Pending]) -> Result<Journal> {
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(RenameError::Interrupted(path.to_path_buf()));
+            }
+            file => file?,
+        };
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut lines = String::new();
+        for op in ops {
+            let entry = Entry::Planned { from: absolute(&op.old_path), to: absolute(&op.new_path) };
+            lines.push_str(&serde_json::to_string(&entry).map_err(|e| RenameError::Journal(path.to_path_buf(), e))?);
+            lines.push('\n');
+        }
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()?;
+        Ok(Journal { path: path.to_path_buf(), file: Mutex::new(file) })
+    }
+
+    /**
+     * Reopens the journal of an interrupted run. A last line cut short by the
+     * interruption is ignored.
+     *
+     * A rename whose file was moved to a temporary name is resumed from there,
+     * never from its old path, which may hold another file by then, e.g. in a
+     * swap. If the file is no longer at the temporary name, it is unknown
+     * where it went, so the rename is given up on.
+     *
+     * @return The journal, the renames left to perform, with where their file
+     *         is now, the `(old, new)` paths of the ones already performed and
+     *         the ones given up on.
+     * @throws RenameError::NotFound if there is no such file.
+     * @throws RenameError::InvalidJournal if the file is not a journal.
+     */
+    pub(crate) fn resume(path: &Path) -> Result<Resumed> {
+        let invalid = || RenameError::InvalidJournal(path.to_path_buf());
+        let file = File::open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => RenameError::NotFound(path.to_path_buf()),
+            _ => e.into(),
+        })?;
+        let lines = BufReader::new(file).lines().collect::<io::Result<Vec<_>>>()?;
+        let mut entries = Vec::with_capacity(lines.len());
+        // how long the file is without a line cut short, if there is one
+        let mut cut_at = None;
+        let mut len = 0;
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<Entry>(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if i + 1 == lines.len() => cut_at = Some(len),
+                Err(_) => return Err(invalid()),
+            }
+            len += line.len() as u64 + 1;
+        }
+        // a journal is only started with a rename to perform
+        if !matches!(entries.first(), Some(Entry::Planned { .. })) {
+            return Err(invalid());
+        }
+
+        let mut ops = Vec::new();
+        let mut done = Vec::new();
+        for entry in entries {
+            match entry {
+                Entry::Planned { from, to } => ops.push(Some(Pending {
+                    index: ops.len(),
+                    current: from.clone(),
+                    old_path: from,
+                    new_path: to,
+                })),
+                Entry::Parked { op, at } => {
+                    let op = ops.get_mut(op).and_then(Option::as_mut).ok_or_else(invalid)?;
+                    op.current = at;
+                }
+                Entry::Done { op } => {
+                    let op = ops.get_mut(op).and_then(Option::take).ok_or_else(invalid)?;
+                    done.push((op.old_path, op.new_path));
+                }
+            }
+        }
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        if let Some(len) = cut_at {
+            file.set_len(len)?;
+        }
+        let journal = Journal { path: path.to_path_buf(), file: Mutex::new(file) };
+        let (ops, lost) = ops.into_iter().flatten()
+            .partition::<Vec<_>, _>(|op| op.current == op.old_path || fs::symlink_metadata(&op.current).is_ok());
+        let lost = lost.into_iter().map(|op| (op.old_path, RenameError::NotFound(op.current))).collect();
+        Ok((journal, ops, done, lost))
+    }
+
+    /**
+     * Records that the file of the rename numbered `op` was moved to `at`.
+     */
+    pub(crate) fn parked(&self, op: usize, at: &Path) -> io::Result<()> {
+        self.append(&Entry::Parked { op, at: std::path::absolute(at)? })
+    }
+
+    /**
+     * Records that the rename numbered `op` was performed.
+     */
+    pub(crate) fn done(&self, op: usize) -> io::Result<()> {
+        self.append(&Entry::Done { op })
+    }
+
+    /**
+     * Removes the journal once the run is over.
+     */
+    pub(crate) fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+
+    fn append(&self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
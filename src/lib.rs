@@ -0,0 +1,1444 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use filetime::FileTime;
+use glob::Pattern;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+
+pub mod conflict;
+pub mod error;
+pub mod exec;
+pub mod filter;
+pub mod hash;
+pub mod journal;
+#[cfg(feature = "exif")]
+pub mod photo;
+mod plan;
+pub mod sort;
+mod stream;
+#[cfg(feature = "id3")]
+pub mod tags;
+pub mod template;
+pub mod transform;
+pub mod undo;
+mod walk;
+
+pub use conflict::{Conflict, ConflictPolicy};
+pub use error::RenameError;
+pub use hash::HashSpec;
+pub use plan::{RenameOp, RenamePlan};
+pub use sort::SortOrder;
+pub use stream::RenameIter;
+pub use template::Template;
+pub use transform::{ExtCase, GroupBy, NumberPosition, Numbering, SeparatorRule, Source, SplitFrom, StemCase,
+                    UnicodeForm};
+use journal::Journal;
+use undo::UndoLog;
+
+pub type Result<T> = std::result::Result<T, RenameError>;
+
+/**
+ * The settings controlling how file names are rewritten.
+ */
+#[derive(Debug, Clone)]
+pub struct RenameOptions {
+    /// Only files ending with one of these extensions are renamed.
+    pub extensions: Vec<String>,
+    /// Whether files with any extension are renamed, whatever `extensions` says.
+    pub any_extension: bool,
+    /// Whether files without an extension, like `README`, are renamed too.
+    pub include_no_ext: bool,
+    /// Whether extensions must match in case too, e.g. `mp3` not matching `.MP3`.
+    pub case_sensitive_ext: bool,
+    /// The extension to give the renamed files instead of their current one,
+    /// or none at all, dot included, if it is empty.
+    pub new_extension: Option<String>,
+    /// How to change the case of the extension, whether it is new or not.
+    pub ext_case: ExtCase,
+    /// Whether to start from the stem as it is instead of swapping its parts,
+    /// e.g. to only change the extension.
+    pub keep_stem: bool,
+    /// Separator to split the file name into parts.
+    pub old_sep: String,
+    /// Separator to join the parts back together.
+    pub new_sep: String,
+    /// The separators to use instead for files with some extensions.
+    pub separator_rules: Vec<SeparatorRule>,
+    /// Pattern to split the file name into parts on instead of the old
+    /// separator, e.g. to split on several kinds of dashes.
+    pub separator_regex: Option<Regex>,
+    /// Which end to split the file name from into only as many parts as the
+    /// order has, or `None` to split it on every separator.
+    pub split_from: Option<SplitFrom>,
+    /// Which part goes where, e.g. `[1, 0]` swaps two parts. Files with a
+    /// different number of parts are skipped, unless a minimum or maximum is given.
+    pub order: Vec<usize>,
+    /// The fewest parts a file may split into, if the number of parts may differ from the order.
+    pub min_parts: Option<usize>,
+    /// The most parts a file may split into, if the number of parts may differ from the order.
+    pub max_parts: Option<usize>,
+    /// Whether to reverse an earlier run with these separators and order:
+    /// split on the new separator, join with the old one without padding, and
+    /// put the parts back where they came from. Files the undo log says were
+    /// renamed are not left alone then.
+    pub reverse: bool,
+    /// Padding to put around the new separator.
+    pub padding: String,
+    /// Whether to rename files in subdirectories too.
+    pub recursive: bool,
+    /// How many levels of subdirectories to descend into when recursive, or `None` for no limit.
+    pub max_depth: Option<usize>,
+    /// Whether to descend into symlinked directories when recursive.
+    pub follow_symlinks: bool,
+    /// Whether to rename directories too, in place and after what is in them.
+    pub include_dirs: bool,
+    /// The directory to move the renamed files to instead of leaving them where they are.
+    pub target_dir: Option<PathBuf>,
+    /// The directory to copy every file into before it is renamed, if any.
+    pub backup_dir: Option<PathBuf>,
+    /// Whether to create the target directory, or the directory of any new
+    /// path, if it does not exist.
+    pub create_dirs: bool,
+    /// Whether to compare the digest of a file copied to another filesystem
+    /// with the original's before deleting the original.
+    pub verify: bool,
+    /// Whether to give a file copied to another filesystem the modification and
+    /// access times of the original, which a rename keeps anyway.
+    pub preserve_timestamps: bool,
+    /// Whether to recreate the subdirectories the files are in below the
+    /// target directory instead of moving them all into it.
+    pub mirror_dirs: bool,
+    /// Whether to move the renamed files found in subdirectories up into the
+    /// top directory when there is no target directory.
+    pub flatten: bool,
+    /// The order to process, and so number, the files in each directory in.
+    pub sort: SortOrder,
+    /// Whether to process the files in the reverse of the sort order, e.g. newest first.
+    pub reverse_sort: bool,
+    /// The most files to rename, the first ones in the sort order that can be
+    /// renamed, or `None` for no limit.
+    pub limit: Option<usize>,
+    /// Whether to leave hidden files and directories alone.
+    pub skip_hidden: bool,
+    /// Whether to only compute the renames instead of performing them.
+    pub dry_run: bool,
+    /// Whether to record performed renames in an undo log inside the directory.
+    pub write_undo_log: bool,
+    /// What to do when a rename would replace another file.
+    pub on_conflict: ConflictPolicy,
+    /// The suffix added to the new name by `ConflictPolicy::Rename`, its `{n}`
+    /// replaced by the first number making the name free.
+    pub conflict_suffix: String,
+    /// The number of threads to rename with, or `None` for one per CPU.
+    pub jobs: Option<usize>,
+    /// How many more times to try a rename that failed in a way that may not
+    /// last, e.g. because the file was busy.
+    pub retries: u32,
+    /// How long to wait before each retry.
+    pub retry_delay: Duration,
+    /// If not empty, only files whose name matches one of these patterns are renamed.
+    pub include: Vec<Pattern>,
+    /// Files whose name matches any of these patterns are left alone.
+    pub exclude: Vec<Pattern>,
+    /// Only files of at least this many bytes are renamed.
+    pub min_size: Option<u64>,
+    /// Only files of at most this many bytes are renamed.
+    pub max_size: Option<u64>,
+    /// Only files modified less than this long ago are renamed.
+    pub newer_than: Option<Duration>,
+    /// Only files modified more than this long ago are renamed.
+    pub older_than: Option<Duration>,
+    /// Only files modified after this time, e.g. when the last run started, are renamed.
+    pub modified_since: Option<SystemTime>,
+    /// Pattern matched against the stem instead of splitting it on the separator.
+    pub regex: Option<Regex>,
+    /// Template the regex captures are substituted into, e.g. `$2 - $1`.
+    pub replace: String,
+    /// Template filled in from the ID3 tags of each file instead of splitting its name.
+    #[cfg(feature = "id3")]
+    pub from_id3: Option<Template>,
+    /// Date format, e.g. `%Y-%m-%d_%H%M%S`, to name each photo after its EXIF
+    /// capture date instead of splitting its name. Photos taken at the same
+    /// moment get a `_2`, `_3`, ... suffix.
+    #[cfg(feature = "exif")]
+    pub from_exif: Option<String>,
+    /// Template for the new stem instead of swapping parts, with `{stem}`,
+    /// `{ext}`, `{part0}`, `{part1}`, ..., `{n}`, `{parent}` and `{date}` tokens.
+    pub template: Option<Template>,
+    /// Template for the new stem of the files that do not split into the
+    /// expected parts or do not match the regex, instead of skipping them.
+    pub fallback_template: Option<Template>,
+    /// Shell command that gets the stem on stdin and prints the new one, run
+    /// before the other stem changes.
+    pub exec: Option<String>,
+    /// How long to let the command run on a file before skipping it.
+    pub exec_timeout: Duration,
+    /// The characters to remove from both ends of the new stem, whitespace if
+    /// empty, or `None` to keep them.
+    pub strip_chars: Option<String>,
+    /// `(find, replace_with)` pairs of literal text to replace in the new stem, in order.
+    pub replacements: Vec<(String, String)>,
+    /// Whether to collapse runs of whitespace in the new stem and trim it.
+    pub squeeze_spaces: bool,
+    /// The character to replace each of the separator characters in the new
+    /// stem with, collapsing runs of it, if any.
+    pub normalize_separators: Option<char>,
+    /// The characters `normalize_separators` replaces.
+    pub separator_chars: String,
+    /// How to change the case of the new stem.
+    pub case: StemCase,
+    /// Text to insert into the new stem, at `insert_at`, if any.
+    pub insert: Option<String>,
+    /// The character position to insert the text at, counting from the end if negative.
+    pub insert_at: isize,
+    /// The digest of the contents to append to the new stem, after `_`, if any.
+    pub append_hash: Option<HashSpec>,
+    /// Text to put before the new stem.
+    pub prefix: String,
+    /// Text to put after the new stem.
+    pub suffix: String,
+    /// What to replace the characters Windows does not allow in file names
+    /// with, or `None` to keep them. Names Windows reserves, like `CON`, get
+    /// a `_` appended too.
+    pub sanitize: Option<String>,
+    /// Whether to replace accented letters in the new stem with ASCII ones,
+    /// removing the characters that have none.
+    pub ascii_fold: bool,
+    /// Whether to turn the new stem into a lowercase, dash-separated, ASCII-only slug.
+    pub slugify: bool,
+    /// The Unicode normalization form to put the new stem in, if any.
+    pub normalize: Option<UnicodeForm>,
+    /// What the sequence numbers look like.
+    pub numbering: Numbering,
+    /// Where to insert the sequence number into each new stem, or `None` to not insert it.
+    pub number_position: Option<NumberPosition>,
+    /// How to split the files of each directory into groups that are numbered
+    /// separately, or `None` to number them all in one sequence.
+    pub group_by: Option<GroupBy>,
+    /// Whether to keep the stems as they are and replace the number each one
+    /// already has with the sequence number, counting in the sort order.
+    pub renumber: bool,
+    /// Pattern finding the number to replace, in its first group if it has one,
+    /// instead of the digits at the start of the stem.
+    pub renumber_regex: Option<Regex>,
+    /// Whether files without a number to replace get one after the others
+    /// instead of being skipped.
+    pub renumber_unnumbered: bool,
+    /// The longest new file name allowed, in bytes. Longer stems are cut short,
+    /// keeping the extension.
+    pub max_name_len: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        RenameOptions {
+            extensions: vec!["mp3".to_string()],
+            any_extension: false,
+            include_no_ext: false,
+            case_sensitive_ext: false,
+            new_extension: None,
+            ext_case: ExtCase::default(),
+            keep_stem: false,
+            old_sep: "-".to_string(),
+            new_sep: "-".to_string(),
+            separator_rules: Vec::new(),
+            separator_regex: None,
+            split_from: None,
+            order: vec![1, 0],
+            min_parts: None,
+            max_parts: None,
+            reverse: false,
+            padding: String::new(),
+            recursive: false,
+            max_depth: None,
+            follow_symlinks: false,
+            include_dirs: false,
+            target_dir: None,
+            backup_dir: None,
+            create_dirs: false,
+            verify: false,
+            preserve_timestamps: false,
+            mirror_dirs: false,
+            flatten: false,
+            sort: SortOrder::default(),
+            reverse_sort: false,
+            limit: None,
+            skip_hidden: true,
+            dry_run: false,
+            write_undo_log: true,
+            on_conflict: ConflictPolicy::default(),
+            conflict_suffix: " ({n})".to_string(),
+            jobs: None,
+            retries: 0,
+            retry_delay: Duration::from_millis(100),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            modified_since: None,
+            older_than: None,
+            regex: None,
+            replace: String::new(),
+            #[cfg(feature = "id3")]
+            from_id3: None,
+            #[cfg(feature = "exif")]
+            from_exif: None,
+            template: None,
+            fallback_template: None,
+            exec: None,
+            exec_timeout: Duration::from_secs(10),
+            strip_chars: None,
+            replacements: Vec::new(),
+            squeeze_spaces: false,
+            normalize_separators: None,
+            separator_chars: "-_.".to_string(),
+            case: StemCase::default(),
+            insert: None,
+            insert_at: 0,
+            append_hash: None,
+            prefix: String::new(),
+            suffix: String::new(),
+            sanitize: None,
+            ascii_fold: false,
+            slugify: false,
+            normalize: None,
+            numbering: Numbering::default(),
+            number_position: None,
+            group_by: None,
+            renumber: false,
+            renumber_regex: None,
+            renumber_unnumbered: false,
+            max_name_len: 255,
+        }
+    }
+}
+
+/**
+ * What happened to the files visited during a run.
+ */
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    /// The `(old, new)` paths of every rename performed, or planned in a dry run.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// Files that were left alone, and why.
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+    /// Renames that would have replaced another file.
+    pub conflicts: Vec<Conflict>,
+    /// Files with a matching extension that matched an exclude pattern.
+    pub excluded: Vec<PathBuf>,
+    /// Files that could not be renamed, and why.
+    pub errors: Vec<(PathBuf, RenameError)>,
+    /// Files whose rename was turned down by the decision or confirmation callback.
+    pub declined: Vec<PathBuf>,
+    /// Files whose new name was cut short to fit the maximum length.
+    pub truncated: Vec<PathBuf>,
+    /// Renamed files that were copied to another filesystem and deleted, so
+    /// not moved atomically.
+    pub copied: Vec<PathBuf>,
+    /// The files copied before being renamed, and where the copy is.
+    pub backed_up: Vec<(PathBuf, PathBuf)>,
+    /// Renamed files that needed retries, and how many.
+    pub retried: Vec<(PathBuf, u32)>,
+    /// Files whose new name lost characters that could not be folded to ASCII, and which.
+    pub dropped: Vec<(PathBuf, String)>,
+    /// Files whose new stem was too short to insert the text where asked, so it went at the start or end.
+    pub clamped: Vec<PathBuf>,
+    /// The size in bytes of every file planned to be renamed, by its old path.
+    pub sizes: HashMap<PathBuf, u64>,
+}
+
+impl RenameReport {
+    /**
+     * Adds what happened in another run, e.g. in another directory, to this report.
+     */
+    pub fn merge(&mut self, other: RenameReport) {
+        self.renamed.extend(other.renamed);
+        self.skipped.extend(other.skipped);
+        self.conflicts.extend(other.conflicts);
+        self.excluded.extend(other.excluded);
+        self.errors.extend(other.errors);
+        self.declined.extend(other.declined);
+        self.truncated.extend(other.truncated);
+        self.copied.extend(other.copied);
+        self.backed_up.extend(other.backed_up);
+        self.retried.extend(other.retried);
+        self.dropped.extend(other.dropped);
+        self.clamped.extend(other.clamped);
+        self.sizes.extend(other.sizes);
+    }
+
+    /**
+     * Counts the files in the report by what happened to them.
+     */
+    pub fn summary(&self) -> Summary {
+        let unchanged = self.skipped.iter().filter(|(_, reason)| *reason == SkipReason::AlreadyNamed).count();
+        Summary {
+            renamed: self.renamed.len(),
+            unchanged,
+            skipped: self.skipped.len() - unchanged + self.declined.len() + self.skipped_conflicts().len(),
+            errors: self.errors.len(),
+            excluded: self.excluded.len(),
+            bytes: self.renamed.iter().filter_map(|(old_path, _)| self.sizes.get(old_path)).sum(),
+        }
+    }
+
+    /**
+     * The files involved in a conflict that were left alone, which is all of them
+     * unless the conflict policy is to overwrite.
+     */
+    pub fn skipped_conflicts(&self) -> Vec<(&Path, &Conflict)> {
+        let renamed = self.renamed.iter().map(|(old_path, _)| old_path).collect::<HashSet<_>>();
+        self.conflicts.iter()
+            .flat_map(|conflict| conflict.sources.iter().map(move |source| (source, conflict)))
+            .filter(|(source, _)| !renamed.contains(source))
+            .map(|(source, conflict)| (source.as_path(), conflict))
+            .collect()
+    }
+}
+
+/**
+ * How many files a run renamed, left alone, failed on and excluded, and how big the renamed ones are.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// The files renamed, or that would be in a dry run.
+    pub renamed: usize,
+    /// The files whose new name is the one they have, which are not touched.
+    pub unchanged: usize,
+    /// The files left alone otherwise: skipped, declined or in a conflict.
+    pub skipped: usize,
+    /// The files that could not be renamed.
+    pub errors: usize,
+    /// The files with a matching extension that matched an exclude pattern.
+    pub excluded: usize,
+    /// The total size of the renamed files in bytes, which is how much is copied
+    /// if they are all moved to another filesystem.
+    pub bytes: u64,
+}
+
+/**
+ * Why a file was left alone.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The name does not split into as many parts as the order expects.
+    WrongPartCount,
+    /// The name splits into fewer parts than the minimum.
+    TooFewParts { parts: usize, min: usize },
+    /// The name splits into more parts than the maximum.
+    TooManyParts { parts: usize, max: usize },
+    /// The name does not match the regex.
+    NoRegexMatch,
+    /// The tags could not be read.
+    UnreadableTags(String),
+    /// The template needs a tag the file does not have.
+    MissingTag(String),
+    /// The template has a token there is no value for, e.g. a missing `{part2}`.
+    MissingToken(String),
+    /// The file is smaller than the minimum size.
+    TooSmall,
+    /// The file is larger than the maximum size.
+    TooLarge,
+    /// The file was modified longer ago than the newest age allowed.
+    ModifiedTooLongAgo,
+    /// The file was modified more recently than the oldest age allowed.
+    ModifiedTooRecently,
+    /// The file was not modified since the given time, e.g. the last run.
+    NotModifiedSince,
+    /// The new name is the current one.
+    AlreadyNamed,
+    /// The file got its name from an earlier run, which renaming it again could undo.
+    AlreadyRenamed,
+    /// The listed path does not exist.
+    NotFound,
+    /// The listed path is a directory or something else that is not a file.
+    NotAFile,
+    /// The file does not have one of the extensions.
+    WrongExtension,
+    /// The file or directory is hidden.
+    Hidden,
+    /// The file name matches none of the include patterns.
+    NotIncluded,
+    /// The external command could not make a new stem, e.g. because it failed.
+    CommandFailed(String),
+    /// The new stem, e.g. printed by the external command or made by a
+    /// replacement, has a path separator or is `..`, so would move the file elsewhere.
+    PathInName(String),
+    /// Another file took the new name after the renames were planned.
+    TargetExists,
+    /// As many files as the limit allows are renamed already.
+    OverLimit,
+    /// The name has no number to renumber.
+    NoNumber,
+    /// The sequence number would be larger than the largest number there is.
+    NumberTooLarge,
+    /// Nothing would be left of the new name but its extension, if any.
+    EmptyName,
+    /// Another file would get the same new name, or already has it.
+    Conflict,
+}
+
+impl SkipReason {
+    /**
+     * A short name for the kind of reason, e.g. `wrong-part-count`, to group skipped files by.
+     */
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkipReason::WrongPartCount => "wrong-part-count",
+            SkipReason::TooFewParts { .. } => "too-few-parts",
+            SkipReason::TooManyParts { .. } => "too-many-parts",
+            SkipReason::NoRegexMatch => "no-regex-match",
+            SkipReason::UnreadableTags(_) => "unreadable-tags",
+            SkipReason::MissingTag(_) => "missing-tag",
+            SkipReason::MissingToken(_) => "missing-token",
+            SkipReason::TooSmall => "too-small",
+            SkipReason::TooLarge => "too-large",
+            SkipReason::ModifiedTooLongAgo => "too-old",
+            SkipReason::ModifiedTooRecently => "too-new",
+            SkipReason::NotModifiedSince => "not-modified",
+            SkipReason::AlreadyNamed => "already-named",
+            SkipReason::AlreadyRenamed => "already-renamed",
+            SkipReason::NotFound => "not-found",
+            SkipReason::NotAFile => "not-a-file",
+            SkipReason::WrongExtension => "extension-mismatch",
+            SkipReason::Hidden => "hidden",
+            SkipReason::NotIncluded => "not-included",
+            SkipReason::CommandFailed(_) => "command-failed",
+            SkipReason::PathInName(_) => "path-in-name",
+            SkipReason::TargetExists => "target-exists",
+            SkipReason::OverLimit => "over-limit",
+            SkipReason::NoNumber => "no-number",
+            SkipReason::NumberTooLarge => "number-too-large",
+            SkipReason::EmptyName => "empty-name",
+            SkipReason::Conflict => "conflict",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::WrongPartCount => write!(f, "wrong number of parts"),
+            SkipReason::TooFewParts { parts, min } => write!(f, "fewer than {} parts ({})", min, parts),
+            SkipReason::TooManyParts { parts, max } => write!(f, "more than {} parts ({})", max, parts),
+            SkipReason::NoRegexMatch => write!(f, "does not match the regex"),
+            SkipReason::UnreadableTags(e) => write!(f, "cannot read tags: {}", e),
+            SkipReason::MissingTag(tag) => write!(f, "missing `{}` tag", tag),
+            SkipReason::MissingToken(token) => write!(f, "no value for `{{{}}}`", token),
+            SkipReason::TooSmall => write!(f, "smaller than the minimum size"),
+            SkipReason::TooLarge => write!(f, "larger than the maximum size"),
+            SkipReason::ModifiedTooLongAgo => write!(f, "modified too long ago"),
+            SkipReason::ModifiedTooRecently => write!(f, "modified too recently"),
+            SkipReason::NotModifiedSince => write!(f, "not modified since the last run"),
+            SkipReason::AlreadyNamed => write!(f, "already named"),
+            SkipReason::AlreadyRenamed => write!(f, "already renamed by an earlier run"),
+            SkipReason::NotFound => write!(f, "does not exist"),
+            SkipReason::NotAFile => write!(f, "is not a file"),
+            SkipReason::WrongExtension => write!(f, "does not have a matching extension"),
+            SkipReason::Hidden => write!(f, "hidden"),
+            SkipReason::NotIncluded => write!(f, "does not match an include pattern"),
+            SkipReason::CommandFailed(message) => write!(f, "{}", message),
+            SkipReason::PathInName(stem) => write!(f, "the new name `{}` is a path, not a file name", stem),
+            SkipReason::TargetExists => write!(f, "the new name is taken"),
+            SkipReason::OverLimit => write!(f, "over the limit"),
+            SkipReason::NoNumber => write!(f, "has no number to renumber"),
+            SkipReason::NumberTooLarge => write!(f, "the sequence number is too large"),
+            SkipReason::EmptyName => write!(f, "the new name would be empty"),
+            SkipReason::Conflict => write!(f, "the new name is contested"),
+        }
+    }
+}
+
+/**
+ * The answer to whether a planned rename should go ahead.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirmation {
+    /// Rename this file.
+    Yes,
+    /// Leave this file alone.
+    No,
+    /// Rename this file and all the remaining ones without asking again.
+    All,
+    /// Leave this file and all the remaining ones alone.
+    Quit,
+}
+
+/**
+ * What to do with a planned rename, as decided by code instead of a prompt.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Rename the file as planned.
+    Accept,
+    /// Leave the file alone.
+    Skip,
+    /// Rename the file to this path instead.
+    Rename(PathBuf),
+}
+
+/**
+ * Checks that the files can be renamed in the given directory at all.
+ *
+ * @throws RenameError::NotFound if the directory does not exist.
+ * @throws RenameError::NotADirectory if it is something else.
+ */
+pub fn check_directory(directory: &Path) -> Result<()> {
+    match fs::metadata(directory) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(RenameError::NotADirectory(directory.to_path_buf())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(RenameError::NotFound(directory.to_path_buf())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/**
+ * Renames the files inside the given directory by splitting each file name on
+ * the old separator and joining the parts back together, reordered, with the
+ * new separator, or by substituting regex captures into a template. All
+ * renames are planned before any is performed, so renames that would replace
+ * another file are handled by the conflict policy up front. Performed renames
+ * are recorded in the undo log unless this is a dry run or the log is
+ * disabled. Files the log says were renamed by an earlier run are not renamed
+ * back, so running the same swap twice is harmless. Files that fail are
+ * recorded in the report and do not stop the rest of the run.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ *
+ * @return The renamed, skipped, and failed files.
+ * @throws RenameError if the directory or target directory does not exist,
+ *         the separator is empty, the order, template or date format is
+ *         invalid, a conflict aborts the run, the undo log cannot be used, or
+ *         the directory cannot be read.
+ */
+pub fn rename_files_swapped(directory: &Path, options: &RenameOptions) -> Result<RenameReport> {
+    rename_files_with_decider(directory, options, &mut |_| Decision::Accept)
+}
+
+/**
+ * Like `rename_files_swapped`, but lets the callback keep, skip or redirect
+ * every planned rename before conflicts are resolved.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ * @param decide Called with each planned rename to keep it, leave the file
+ *        alone or give it another new path.
+ *
+ * @return The renamed, skipped, declined, and failed files.
+ * @throws RenameError in the same cases as `rename_files_swapped`.
+ */
+pub fn rename_files_with_decider(directory: &Path, options: &RenameOptions,
+                                 decide: &mut dyn FnMut(&RenameOp) -> Decision) -> Result<RenameReport> {
+    check_directory(directory)?;
+    check_options(options)?;
+    rename_collected(directory, options, decide, &mut |_, _| Confirmation::All, |collector| collector.collect())
+}
+
+/**
+ * Like `rename_files_swapped`, but asks the callback about every planned rename
+ * that survived the conflict policy, in order, before any file is renamed.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ * @param confirm Called with the old and new path of each planned rename.
+ *
+ * @return The renamed, skipped, declined, and failed files.
+ * @throws RenameError in the same cases as `rename_files_swapped`.
+ */
+pub fn rename_files_confirmed(directory: &Path, options: &RenameOptions,
+                              confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation) -> Result<RenameReport> {
+    check_directory(directory)?;
+    check_options(options)?;
+    rename_collected(directory, options, &mut |_| Decision::Accept, confirm, |collector| collector.collect())
+}
+
+/**
+ * Like `rename_files_confirmed`, but renames the given files instead of the
+ * ones found in a directory. Listed paths that do not exist, are not files or do
+ * not have one of the extensions are skipped.
+ *
+ * @param paths The files to rename, in order.
+ * @param directory The directory to keep the undo log in, which paths in it are recorded relative to,
+ *        and whose layout `mirror_dirs` recreates.
+ * @param options The extensions, separators, padding, and flags to use.
+ * @param confirm Called with the old and new path of each planned rename.
+ *
+ * @return The renamed, skipped, declined, and failed files.
+ * @throws RenameError in the same cases as `rename_files_swapped`.
+ */
+pub fn rename_listed_files(paths: Vec<PathBuf>, directory: &Path, options: &RenameOptions,
+                           confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation) -> Result<RenameReport> {
+    check_directory(directory)?;
+    check_options(options)?;
+    rename_collected(directory, options, &mut |_| Decision::Accept, confirm,
+                     |collector| Ok(collector.collect_files(paths)))
+}
+
+/**
+ * Works out the renames `rename_files_swapped` would perform in the given
+ * directory, without touching any file.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ *
+ * @return The plan, to be applied with `RenamePlan::apply`.
+ * @throws RenameError in the same cases as `rename_files_swapped`, except for the ones that only happen while renaming.
+ */
+pub fn plan(directory: &Path, options: &RenameOptions) -> Result<RenamePlan> {
+    plan_with_decider(directory, options, &mut |_| Decision::Accept)
+}
+
+/**
+ * Like `plan`, but lets the callback keep, skip or redirect every planned
+ * rename, as in `rename_files_with_decider`.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ * @param decide Called with each planned rename.
+ *
+ * @return The plan, to be applied with `RenamePlan::apply`.
+ * @throws RenameError in the same cases as `plan`.
+ */
+pub fn plan_with_decider(directory: &Path, options: &RenameOptions,
+                         decide: &mut dyn FnMut(&RenameOp) -> Decision) -> Result<RenamePlan> {
+    check_directory(directory)?;
+    check_options(options)?;
+    plan_collected(directory, options, decide, |collector| collector.collect())
+}
+
+/**
+ * Like `plan`, but works out the renames lazily, one directory at a time as
+ * they are iterated over, instead of all of them up front, for trees too large
+ * to hold every rename of in memory. Each rename can then be inspected and
+ * applied, e.g. with `plan_renames`, before the next one is worked out.
+ *
+ * @param directory The directory in which to rename files.
+ * @param options The extensions, separators, padding, and flags to use.
+ *
+ * @return The renames, followed in each directory by the files left alone and why, or the
+ *         errors met along the way. Iteration ends after an error that stops the planning,
+ *         e.g. a directory that cannot be read or a conflict that aborts the run.
+ * @throws RenameError in the same cases as `plan`, as far as they can be found before walking the tree.
+ */
+pub fn rename_iter<'a>(directory: &'a Path, options: &'a RenameOptions) -> Result<RenameIter<'a>> {
+    check_directory(directory)?;
+    check_options(options)?;
+    RenameIter::new(directory, options)
+}
+
+/**
+ * Like `plan`, but for the given files instead of the ones found in a
+ * directory, as `rename_listed_files` would rename them.
+ *
+ * @param paths The files to rename, in order.
+ * @param directory The directory to keep the undo log in.
+ * @param options The extensions, separators, padding, and flags to use.
+ *
+ * @return The plan, to be applied with `RenamePlan::apply`.
+ * @throws RenameError in the same cases as `plan`.
+ */
+pub fn plan_listed_files(paths: Vec<PathBuf>, directory: &Path, options: &RenameOptions) -> Result<RenamePlan> {
+    plan_listed_files_with_decider(paths, directory, options, &mut |_| Decision::Accept)
+}
+
+/**
+ * Like `plan_listed_files`, but lets the callback keep, skip or redirect every
+ * planned rename, as in `rename_files_with_decider`.
+ *
+ * @param paths The files to rename, in order.
+ * @param directory The directory to keep the undo log in.
+ * @param options The extensions, separators, padding, and flags to use.
+ * @param decide Called with each planned rename.
+ *
+ * @return The plan, to be applied with `RenamePlan::apply`.
+ * @throws RenameError in the same cases as `plan`.
+ */
+pub fn plan_listed_files_with_decider(paths: Vec<PathBuf>, directory: &Path, options: &RenameOptions,
+                                      decide: &mut dyn FnMut(&RenameOp) -> Decision) -> Result<RenamePlan> {
+    check_directory(directory)?;
+    check_options(options)?;
+    plan_collected(directory, options, decide, |collector| Ok(collector.collect_files(paths)))
+}
+
+/**
+ * Plans exactly the given renames instead of working them out from the file
+ * names, e.g. ones edited by hand. Renames of files that do not exist, or to
+ * the name they already have, are left alone, and the conflict policy applies
+ * as usual.
+ *
+ * @param renames The `(old, new)` paths to rename, in order.
+ * @param directory The directory to keep the undo log in.
+ * @param options The flags to rename with; the ones that work out the new names are ignored.
+ *
+ * @return The plan, to be applied with `RenamePlan::apply`.
+ * @throws RenameError::NotFound if the directory does not exist.
+ * @throws RenameError::Conflict if a rename would replace another file and the policy is `Abort`.
+ */
+pub fn plan_renames(renames: Vec<(PathBuf, PathBuf)>, directory: &Path, options: &RenameOptions) -> Result<RenamePlan> {
+    check_directory(directory)?;
+    let mut report = RenameReport::default();
+    let mut left_alone = Vec::new();
+    let mut ops = Vec::with_capacity(renames.len());
+    for (from, to) in renames {
+        let reason = match fs::symlink_metadata(&from) {
+            Err(_) => SkipReason::NotFound,
+            Ok(_) if from == to => SkipReason::AlreadyNamed,
+            Ok(metadata) => {
+                report.sizes.insert(from.clone(), metadata.len());
+                ops.push((from, to));
+                continue;
+            }
+        };
+        left_alone.push(RenameOp { from, to, reason: Some(reason) });
+    }
+    report.conflicts = conflict::resolve_conflicts(&mut ops, options)?;
+    let ops = ops.into_iter()
+        .map(|(from, to)| RenameOp { from, to, reason: None })
+        .chain(left_alone)
+        .collect();
+    Ok(RenamePlan { ops, report, directory: directory.to_path_buf(), options: options.clone() })
+}
+
+/**
+ * Checks the options that would make every rename fail or go wrong.
+ */
+fn check_options(options: &RenameOptions) -> Result<()> {
+    check_separators(options)?;
+    if !transform::is_permutation(&options.order) {
+        return Err(RenameError::InvalidOrder(options.order.clone()));
+    }
+    #[cfg(feature = "id3")]
+    if let Some(template) = &options.from_id3 {
+        template.check_tokens(|token| tags::ID3_TOKENS.contains(&token))?;
+    }
+    #[cfg(feature = "exif")]
+    if let Some(format) = &options.from_exif {
+        photo::check_date_format(format)?;
+    }
+    for template in options.template.iter().chain(&options.fallback_template) {
+        template.check_tokens(transform::is_template_token)?;
+    }
+    if let Some(target_dir) = &options.target_dir {
+        if !options.create_dirs || target_dir.exists() {
+            check_directory(target_dir)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Checks that the file names can be split on the separators, which an empty
+ * one, e.g. left by a comma at the start of `,-`, or a regex matching nothing cannot.
+ *
+ * @throws RenameError::InvalidSeparator if a separator to split on is empty or the regex matches an empty string.
+ */
+pub fn check_separators(options: &RenameOptions) -> Result<()> {
+    let old_seps = std::iter::once(&options.old_sep).chain(options.separator_rules.iter().map(|rule| &rule.old_sep));
+    for old_sep in old_seps {
+        if old_sep.is_empty() {
+            return Err(RenameError::InvalidSeparator(old_sep.clone()));
+        }
+    }
+    if let Some(regex) = options.separator_regex.as_ref().filter(|regex| regex.is_match("")) {
+        return Err(RenameError::InvalidSeparator(regex.to_string()));
+    }
+    Ok(())
+}
+
+/**
+ * Plans the renames with `collect`, resolves conflicts, asks for confirmation,
+ * performs them and records them in the undo log inside `directory`.
+ */
+fn rename_collected(directory: &Path, options: &RenameOptions,
+                    decide: &mut dyn FnMut(&RenameOp) -> Decision,
+                    confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+                    collect: impl FnOnce(walk::Collector) -> Result<(Vec<(PathBuf, PathBuf)>, RenameReport)>)
+                    -> Result<RenameReport> {
+    plan_collected(directory, options, decide, collect)?.perform(confirm, &|| {})
+}
+
+/**
+ * Plans the renames with `collect`, leaves out the ones the undo log says
+ * were already made, lets `decide` change the rest and resolves conflicts.
+ */
+fn plan_collected(directory: &Path, options: &RenameOptions,
+                  decide: &mut dyn FnMut(&RenameOp) -> Decision,
+                  collect: impl FnOnce(walk::Collector) -> Result<(Vec<(PathBuf, PathBuf)>, RenameReport)>)
+                  -> Result<RenamePlan> {
+    let undo_log = UndoLog::load_or_default(&directory.join(undo::UNDO_LOG_NAME))?;
+
+    let (ops, mut report) = collect(walk::Collector::new(options, directory))?;
+    let (ops, left_alone) = skip_renamed(ops, directory, &undo_log, options.reverse);
+    let mut ops = decide_renames(ops, decide, &mut report);
+    report.conflicts = conflict::resolve_conflicts(&mut ops, options)?;
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let ops = ops.into_iter()
+        .enumerate()
+        .map(|(i, (from, to))| RenameOp { from, to, reason: (i >= limit).then_some(SkipReason::OverLimit) })
+        .chain(left_alone)
+        .collect();
+    Ok(RenamePlan { ops, report, directory: directory.to_path_buf(), options: options.clone() })
+}
+
+/**
+ * Splits off the renames that would not change the name, and, unless `reverse`
+ * asks for it, the ones of files that got their name from a rename recorded in
+ * the undo log, so rerunning a swap leaves them alone instead of undoing it.
+ *
+ * @return The renames left, and the ones split off with the reason why.
+ */
+fn skip_renamed(ops: Vec<(PathBuf, PathBuf)>, directory: &Path, undo_log: &UndoLog, reverse: bool)
+                -> (Vec<(PathBuf, PathBuf)>, Vec<RenameOp>) {
+    let mut left_alone = Vec::new();
+    let ops = ops.into_iter()
+        .filter_map(|(old_path, new_path)| {
+            let reason = if old_path == new_path {
+                SkipReason::AlreadyNamed
+            } else if !reverse && undo_log.was_renamed(directory, &old_path) {
+                SkipReason::AlreadyRenamed
+            } else {
+                return Some((old_path, new_path));
+            };
+            left_alone.push(RenameOp { from: old_path, to: new_path, reason: Some(reason) });
+            None
+        })
+        .collect();
+    (ops, left_alone)
+}
+
+/**
+ * Performs the renames, unless this is a dry run, keeping a journal of them
+ * inside `directory` while doing so, and records them in the undo log there
+ * unless the log is disabled.
+ *
+ * @throws RenameError::Interrupted if an earlier run left its journal behind.
+ */
+fn apply_renames(ops: Vec<(PathBuf, PathBuf)>, directory: &Path, options: &RenameOptions,
+                 report: &mut RenameReport, progress: &(dyn Fn() + Sync)) -> Result<()> {
+    if options.dry_run || ops.is_empty() {
+        report.renamed.extend(ops);
+        return Ok(());
+    }
+
+    let ops = ops.into_iter().enumerate()
+        .map(|(index, (old_path, new_path))| Pending { index, current: old_path.clone(), old_path, new_path })
+        .collect::<Vec<_>>();
+    let journal = Journal::create(&directory.join(journal::JOURNAL_NAME), &ops)?;
+    let result = perform_renames(ops, options, report, progress, &journal);
+    finish_run(journal, result, directory, options, &report.renamed)
+}
+
+/**
+ * Confirms and performs the renames as `apply_renames` does, then, as long as
+ * fewer files were renamed than the limit allows, as many of the `reserves`,
+ * the renames planned over the limit, as are missing, in order, so that files
+ * that fail or are skipped do not count. A dry run previews no reserves. The
+ * reserves not needed are skipped as over the limit.
+ */
+fn apply_limited(ops: Vec<(PathBuf, PathBuf)>, reserves: Vec<(PathBuf, PathBuf)>, directory: &Path,
+                 options: &RenameOptions, report: &mut RenameReport,
+                 confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+                 progress: &(dyn Fn() + Sync)) -> Result<()> {
+    let renamed_before = report.renamed.len();
+    let mut answer = Confirmation::Yes;
+    let ops = confirm_renames(ops, confirm, &mut answer, report);
+    apply_renames(ops, directory, options, report, progress)?;
+
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let mut reserves = reserves.into_iter();
+    while !options.dry_run && answer != Confirmation::Quit {
+        let missing = limit.saturating_sub(report.renamed.len() - renamed_before);
+        let ops = reserves.by_ref().take(missing).collect::<Vec<_>>();
+        if ops.is_empty() {
+            break;
+        }
+        let ops = confirm_renames(ops, confirm, &mut answer, report);
+        apply_renames(ops, directory, options, report, progress)?;
+    }
+    report.skipped.extend(reserves.map(|(old_path, _)| (old_path, SkipReason::OverLimit)));
+    Ok(())
+}
+
+/**
+ * Finishes a run that was interrupted, e.g. killed, from the journal it left
+ * in its directory. The renames it had not performed yet are performed and
+ * recorded in the undo log along with the ones it had.
+ *
+ * @param journal_path The journal left by the interrupted run.
+ * @param options How to perform the renames, e.g. the conflict policy, jobs and
+ *        backup directory. The options about what to rename do not matter.
+ *
+ * @return The files renamed now, and the ones that failed again or were lost
+ *         track of, having been moved out of the way and not found there.
+ * @throws RenameError::InvalidJournal if the file is not a journal.
+ * @throws RenameError if the journal or the undo log cannot be used.
+ */
+pub fn resume_renames(journal_path: &Path, options: &RenameOptions) -> Result<RenameReport> {
+    let directory = journal_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let directory = std::path::absolute(directory)?;
+    let (journal, ops, done, lost) = Journal::resume(journal_path)?;
+
+    let mut report = RenameReport { errors: lost, ..RenameReport::default() };
+    let result = perform_renames(ops, options, &mut report, &|| {}, &journal);
+    let renamed = done.into_iter().chain(report.renamed.iter().cloned()).collect::<Vec<_>>();
+    finish_run(journal, result, &directory, options, &renamed)?;
+    Ok(report)
+}
+
+/**
+ * Records the renames in the undo log unless it is disabled, then removes the
+ * journal, unless the renames could not all be attempted.
+ */
+fn finish_run(journal: Journal, result: Result<()>, directory: &Path, options: &RenameOptions,
+              renamed: &[(PathBuf, PathBuf)]) -> Result<()> {
+    // save what was renamed before a possible failure so it can still be undone
+    if options.write_undo_log && !renamed.is_empty() {
+        let undo_path = directory.join(undo::UNDO_LOG_NAME);
+        let mut undo_log = UndoLog::load_or_default(&undo_path)?;
+        for (from, to) in renamed {
+            undo_log.push(directory, from, to);
+        }
+        undo_log.save(&undo_path)?;
+    }
+    if result.is_ok() {
+        journal.finish()?;
+    }
+    result
+}
+
+/**
+ * Keeps or redirects the planned renames as the callback decides, recording
+ * the files it leaves alone as declined.
+ */
+fn decide_renames(ops: Vec<(PathBuf, PathBuf)>, decide: &mut dyn FnMut(&RenameOp) -> Decision,
+                  report: &mut RenameReport) -> Vec<(PathBuf, PathBuf)> {
+    let mut decided = Vec::with_capacity(ops.len());
+    for (from, to) in ops {
+        let op = RenameOp { from, to, reason: None };
+        match decide(&op) {
+            Decision::Accept => decided.push((op.from, op.to)),
+            Decision::Rename(to) => decided.push((op.from, to)),
+            Decision::Skip => report.declined.push(op.from),
+        }
+    }
+    decided
+}
+
+/**
+ * Keeps the planned renames the callback agrees to, recording the others as
+ * declined. `answer` is the last answer, which is not asked again once it is
+ * for all the remaining renames.
+ */
+fn confirm_renames(ops: Vec<(PathBuf, PathBuf)>, confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+                   answer: &mut Confirmation, report: &mut RenameReport) -> Vec<(PathBuf, PathBuf)> {
+    let mut confirmed = Vec::with_capacity(ops.len());
+    for (old_path, new_path) in ops {
+        if matches!(answer, Confirmation::Yes | Confirmation::No) {
+            *answer = confirm(&old_path, &new_path);
+        }
+        match answer {
+            Confirmation::Yes | Confirmation::All => confirmed.push((old_path, new_path)),
+            Confirmation::No | Confirmation::Quit => report.declined.push(old_path),
+        }
+    }
+    confirmed
+}
+
+/**
+ * A rename about to be performed.
+ */
+#[derive(Debug, Clone)]
+pub(crate) struct Pending {
+    /// The position of the rename in the journal.
+    pub(crate) index: usize,
+    pub(crate) old_path: PathBuf,
+    pub(crate) new_path: PathBuf,
+    /// Where the file is now: its old path, unless it was moved out of the way.
+    pub(crate) current: PathBuf,
+}
+
+/**
+ * What became of a planned rename.
+ */
+enum Outcome {
+    /// The file was renamed, and copied if the flag is set, after the given
+    /// number of retries, with the path of its backup if one was made.
+    Renamed(PathBuf, PathBuf, bool, u32, Option<PathBuf>),
+    Skipped(PathBuf, SkipReason),
+    Failed(PathBuf, RenameError),
+}
+
+/**
+ * Performs the renames, recording each one in the report and the journal.
+ * Files are renamed on a thread pool unless a single job is requested, and
+ * directories after them, one by one, deepest first. Files whose name is the
+ * target of another rename, as in a swap, are moved to a temporary name first
+ * so that no file is clobbered whatever order the renames run in. Unless the
+ * conflict policy is to overwrite, a rename whose target has appeared since
+ * planning is skipped. A failed rename is recorded as an error, after retrying
+ * it if it may work later, and does not stop the others. `progress` is called
+ * once each file is done with, from whichever thread handled it.
+ */
+fn perform_renames(ops: Vec<Pending>, options: &RenameOptions, report: &mut RenameReport,
+                   progress: &(dyn Fn() + Sync), journal: &Journal) -> Result<()> {
+    if let Some(backup_dir) = &options.backup_dir {
+        fs::create_dir_all(backup_dir)?;
+    }
+    // built before any file is moved out of the way, so failing leaves none behind
+    let pool = match options.jobs {
+        Some(1) => None,
+        jobs => Some(rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.unwrap_or(0))
+            .build()
+            .map_err(io::Error::other)?),
+    };
+    let (dirs, files) = ops.into_iter().partition::<Vec<_>, _>(|op| op.current.is_dir());
+    let files = vacate_targets(files, report, journal);
+    let rename = |op: Pending| {
+        let index = op.index;
+        let outcome = rename_one(op, options);
+        if let Outcome::Renamed(..) = outcome {
+            // a rename missing from the journal is skipped when resuming, as its target is taken
+            let _ = journal.done(index);
+        }
+        progress();
+        outcome
+    };
+    let mut outcomes = match pool {
+        None => files.into_iter().map(&rename).collect::<Vec<_>>(),
+        Some(pool) => pool.install(|| files.into_par_iter().map(&rename).collect::<Vec<_>>()),
+    };
+    // deepest first, so that no directory is renamed before the ones in it
+    let mut dirs = dirs;
+    dirs.sort_by_key(|op| Reverse(op.old_path.components().count()));
+    for level in dirs.chunk_by(|a, b| a.old_path.components().count() == b.old_path.components().count()) {
+        let level = vacate_targets(level.to_vec(), report, journal);
+        outcomes.extend(level.into_iter().map(&rename));
+    }
+
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Renamed(old_path, new_path, copied, retries, backup) => {
+                if let Some(backup) = backup {
+                    report.backed_up.push((old_path.clone(), backup));
+                }
+                if copied {
+                    report.copied.push(old_path.clone());
+                }
+                if retries > 0 {
+                    report.retried.push((old_path.clone(), retries));
+                }
+                report.renamed.push((old_path, new_path));
+            }
+            Outcome::Skipped(old_path, reason) => report.skipped.push((old_path, reason)),
+            Outcome::Failed(old_path, error) => report.errors.push((old_path, error)),
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Renames a file from where it is now, `current`, moving it back to its old
+ * path if the rename does not happen. Failures that may not last are retried
+ * as many times as the options allow. The file is backed up first if asked
+ * to, and left alone if that fails.
+ */
+fn rename_one(op: Pending, options: &RenameOptions) -> Outcome {
+    let Pending { old_path, new_path, current, .. } = op;
+    let create_dirs = options.create_dirs || (options.target_dir.is_some() && options.mirror_dirs);
+    let clobber = options.on_conflict == ConflictPolicy::Overwrite;
+    // the file is left at its temporary name if another took its old path meanwhile
+    let put_back = |error: RenameError| match restore(&current, &old_path) {
+        Ok(()) => error,
+        Err(_) => RenameError::Stranded(current.clone(), Box::new(error)),
+    };
+    if !clobber && is_taken(&new_path, &current) {
+        return match restore(&current, &old_path) {
+            Ok(()) => Outcome::Skipped(old_path, SkipReason::TargetExists),
+            Err(_) => {
+                let error = RenameError::Stranded(current.clone(), Box::new(RenameError::TargetExists(new_path)));
+                Outcome::Failed(old_path, error)
+            }
+        };
+    }
+    let backup = match &options.backup_dir {
+        // renaming a directory in place loses nothing
+        Some(_) if current.is_dir() => None,
+        Some(backup_dir) => match back_up(&current, &old_path, backup_dir) {
+            Ok(backup) => Some(backup),
+            Err(e) => {
+                let error = put_back(RenameError::from(e));
+                return Outcome::Failed(old_path, error);
+            }
+        },
+        None => None,
+    };
+    let mut retries = 0;
+    let result = loop {
+        match move_file(&current, &new_path, create_dirs, options.verify, options.preserve_timestamps) {
+            Err(e) if retries < options.retries && is_transient(&e) => {
+                retries += 1;
+                thread::sleep(options.retry_delay);
+            }
+            result => break result,
+        }
+    };
+    match result {
+        Ok(copied) => Outcome::Renamed(old_path, new_path, copied, retries, backup),
+        Err(e) => {
+            let error = put_back(RenameError::from(e));
+            Outcome::Failed(old_path, error)
+        }
+    }
+}
+
+/**
+ * Moves a file parked at a temporary name back to its old path, without
+ * replacing a file that took that path in the meantime, e.g. the other file
+ * of a swap.
+ *
+ * @throws io::Error if the old path is taken or the file cannot be moved.
+ */
+fn restore(current: &Path, old_path: &Path) -> io::Result<()> {
+    if current == old_path {
+        return Ok(());
+    }
+    // unlike a rename, a link fails instead of replacing the file there
+    match fs::hard_link(current, old_path) {
+        Ok(()) => fs::remove_file(current),
+        // directories cannot be linked
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists && current.is_dir() => {
+            if fs::symlink_metadata(old_path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("`{}` is taken", old_path.display())));
+            }
+            fs::rename(current, old_path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/**
+ * Copies the file at `current` into the backup directory under the name it had
+ * at `old_path`, with `_2`, `_3`, ... appended to the stem if that is taken.
+ *
+ * @return The path of the copy.
+ */
+fn back_up(current: &Path, old_path: &Path, backup_dir: &Path) -> io::Result<PathBuf> {
+    let file_name = old_path.file_name().unwrap_or_default();
+    let stem = Path::new(file_name).file_stem().unwrap_or_default().to_string_lossy();
+    let extension = Path::new(file_name).extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+    for n in 1.. {
+        let backup = if n == 1 {
+            backup_dir.join(file_name)
+        } else {
+            backup_dir.join(format!("{}_{}{}", stem, n, extension.as_deref().unwrap_or("")))
+        };
+        // claim the name first so that parallel backups cannot pick the same one
+        match fs::OpenOptions::new().write(true).create_new(true).open(&backup) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+        if let Err(e) = fs::copy(current, &backup) {
+            let _ = fs::remove_file(&backup);
+            return Err(e);
+        }
+        return Ok(backup);
+    }
+    unreachable!()
+}
+
+/**
+ * Returns whether the error may go away by itself, e.g. a file being busy, as
+ * opposed to one that does not exist or cannot be accessed.
+ */
+fn is_transient(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::ResourceBusy
+        | io::ErrorKind::TimedOut)
+}
+
+/**
+ * Returns whether another file than `current` is at `path`. On filesystems
+ * that ignore case, a file being renamed to a different case is at both.
+ */
+fn is_taken(path: &Path, current: &Path) -> bool {
+    if fs::symlink_metadata(path).is_err() {
+        return false;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(current)) {
+        (Ok(path), Ok(current)) => path != current,
+        _ => true,
+    }
+}
+
+/**
+ * Renames the file, or copies it and deletes the original when the target is
+ * on another filesystem, which `fs::rename` cannot do. With `create_dirs`, the
+ * directory of the target is created first if needed. With `verify`, a copy
+ * whose contents differ from the original is deleted instead of the original.
+ * With `preserve_timestamps`, a copy gets the modification and access times
+ * of the original, or is deleted instead of it if it cannot. A copy that
+ * fails part way is deleted too, so only the original is left.
+ *
+ * @return Whether the file had to be copied.
+ */
+fn move_file(from: &Path, to: &Path, create_dirs: bool, verify: bool, preserve_timestamps: bool) -> io::Result<bool> {
+    if create_dirs {
+        if let Some(directory) = to.parent() {
+            fs::create_dir_all(directory)?;
+        }
+    }
+    match fs::rename(from, to) {
+        Ok(()) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            // read before the copy, which may count as an access
+            let metadata = fs::metadata(from)?;
+            let copy = || {
+                fs::copy(from, to)?;
+                if verify {
+                    let spec = HashSpec { algorithm: hash::HashAlgorithm::Sha256, len: None };
+                    if hash::digest(from, &spec)? != hash::digest(to, &spec)? {
+                        let message = format!("the copy at `{}` does not match the original", to.display());
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                    }
+                }
+                if preserve_timestamps {
+                    let accessed = FileTime::from_last_access_time(&metadata);
+                    let modified = FileTime::from_last_modification_time(&metadata);
+                    filetime::set_file_times(to, accessed, modified)?;
+                }
+                Ok(())
+            };
+            if let Err(e) = copy() {
+                // leave no partial or unverified copy behind
+                let _ = fs::remove_file(to);
+                return Err(e);
+            }
+            if let Err(e) = fs::remove_file(from) {
+                // keep a single copy, the original
+                let _ = fs::remove_file(to);
+                return Err(e);
+            }
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/**
+ * Moves every file that another rename targets to a temporary name in the same
+ * directory, unless it was moved already, recording where in the journal first.
+ * When a file cannot be moved, the renames onto it are dropped as failed, and
+ * so on for the renames onto their sources, whose files are moved back.
+ *
+ * @return The renames left, with where their file is now.
+ */
+fn vacate_targets(ops: Vec<Pending>, report: &mut RenameReport, journal: &Journal) -> Vec<Pending> {
+    let targets = ops.iter().map(|op| op.new_path.clone()).collect::<HashSet<_>>();
+    // the paths still taken by a file that is not renamed away
+    let mut stuck = HashSet::new();
+    let mut ops = ops.into_iter()
+        .filter_map(|mut op| {
+            if op.current != op.old_path || op.old_path == op.new_path || !targets.contains(&op.old_path) {
+                return Some(op);
+            }
+            let temp_path = temporary_path(&op.old_path, op.index);
+            match journal.parked(op.index, &temp_path).and_then(|()| fs::rename(&op.old_path, &temp_path)) {
+                Ok(()) => {
+                    op.current = temp_path;
+                    Some(op)
+                }
+                Err(e) => {
+                    stuck.insert(op.old_path.clone());
+                    report.errors.push((op.old_path, e.into()));
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    while ops.iter().any(|op| stuck.contains(&op.new_path)) {
+        let (blocked, rest) = ops.into_iter().partition::<Vec<_>, _>(|op| stuck.contains(&op.new_path));
+        for op in blocked {
+            let mut error = RenameError::TargetExists(op.new_path);
+            if restore(&op.current, &op.old_path).is_err() {
+                error = RenameError::Stranded(op.current, Box::new(error));
+            }
+            stuck.insert(op.old_path.clone());
+            report.errors.push((op.old_path, error));
+        }
+        ops = rest;
+    }
+    ops
+}
+
+/**
+ * A name next to the file that is not taken yet, derived from the position of
+ * its rename in the journal.
+ */
+fn temporary_path(path: &Path, i: usize) -> PathBuf {
+    (0..)
+        .map(|attempt| path.with_file_name(format!("{}{}_{}", journal::TEMP_NAME_PREFIX, i, attempt)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * Makes an empty directory for the test to rename files in.
+     */
+    fn test_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("batch_rename_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn a_second_run_leaves_the_renamed_files_alone() {
+        let directory = test_dir("second_run");
+        fs::write(directory.join("a - b.mp3"), "").unwrap();
+        fs::write(directory.join("b - a.mp3"), "").unwrap();
+        let options = RenameOptions::default();
+
+        let first = rename_files_swapped(&directory, &options).unwrap();
+        assert_eq!(first.renamed.len(), 2);
+        let second = rename_files_swapped(&directory, &options).unwrap();
+        assert!(second.renamed.is_empty());
+        let already_renamed = second.skipped.iter().filter(|(_, reason)| *reason == SkipReason::AlreadyRenamed);
+        assert_eq!(already_renamed.count(), 2);
+        assert!(directory.join("a-b.mp3").exists() && directory.join("b-a.mp3").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn a_cycle_of_renames_is_parked_and_completed() {
+        let directory = test_dir("cycle");
+        fs::write(directory.join("a-b.mp3"), "first").unwrap();
+        fs::write(directory.join("b-a.mp3"), "second").unwrap();
+
+        let report = rename_files_swapped(&directory, &RenameOptions::default()).unwrap();
+        assert_eq!(report.renamed.len(), 2);
+        assert!(report.errors.is_empty());
+        assert_eq!(fs::read_to_string(directory.join("b-a.mp3")).unwrap(), "first");
+        assert_eq!(fs::read_to_string(directory.join("a-b.mp3")).unwrap(), "second");
+        let mut left = fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(left, [undo::UNDO_LOG_NAME, "a-b.mp3", "b-a.mp3"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
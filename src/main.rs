@@ -1,8 +1,26 @@
 
-use std::fs;
-use clap::{Parser};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Local};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 
-type Result<T> = std::result::Result<T, std::io::Error>;
+use batch_rename::{check_directory, filter, plan, plan_listed_files, plan_renames, resume_renames, undo, Confirmation,
+                   ConflictPolicy, ExtCase, GroupBy, HashSpec, NumberPosition, Numbering, RenameError, RenameOptions,
+                   RenamePlan, RenameReport, SeparatorRule, SkipReason, SortOrder, SplitFrom, StemCase, Summary,
+                   UnicodeForm};
+use config::Config;
+use output::{ColorChoice, Format, Logger, Verbosity};
+
+mod config;
+mod mapping;
+mod output;
+#[cfg(feature = "watch")]
+mod watch;
 
 #[derive(Debug, Parser)]
 #[clap(name = "batch_renamer",
@@ -11,8 +29,12 @@ type Result<T> = std::result::Result<T, std::io::Error>;
           about = "This is a renaming tool to rename all files inside a folder.",
           )]
 struct Args {
-    #[arg(short, long, default_value = ".", help = "The directory to rename files in")]
-    directory: Option<String>,
+    #[arg(short, long, num_args = 1.., default_value = ".",
+          help = "The directories to rename files in, each on its own with the same options")]
+    directory: Vec<String>,
+    #[arg(long, default_value_t = false,
+          help = "Resolve the directories to absolute paths, without symlinks, so the printed paths are absolute too")]
+    canonicalize: bool,
     #[arg(
         short,
         long,
@@ -20,110 +42,927 @@ struct Args {
         num_args = 1..,
         default_value = "mp3",
         value_delimiter = ',',
+        env = "BATCH_RENAMER_EXTENSIONS",
         help = "Only files ends with the given extensions are to be renamed",)]
     extensions: Vec<String>,
+    #[arg(long, default_value_t = false,
+          help = "Also rename files without an extension, like `README`, as does giving an empty extension, e.g. `-e mp3,`")]
+    include_no_ext: bool,
+    #[arg(long, default_value_t = false, help = "Match the extensions case-sensitively, so `mp3` does not match `.MP3`")]
+    case_sensitive_ext: bool,
+    #[arg(long, value_name = "EXT", help = "Change the extension of the renamed files to the given one, e.g. `jpg`, or drop it with an empty one")]
+    new_extension: Option<String>,
+    #[arg(long, value_enum, default_value_t = ExtCase::Keep,
+          help = "Change the case of the extension of the renamed files, whatever the case of the new file name")]
+    ext_case: ExtCase,
+    #[arg(long, default_value_t = false, conflicts_with_all = ["ext_case", "regex", "template", "renumber"],
+          help = "Only lowercase the extensions, e.g. `.JPG` to `.jpg`, keeping the file names as they are instead of swapping their parts, for files of every extension unless `-e` is given")]
+    lowercase_ext: bool,
     #[arg(short,
           long,
-          value_parser,
-          num_args = 1..2,
-          value_delimiter = ',',
           default_value = "-",
-          help = "The separator to use, e.g. `-` or `.`. But `,` is not allowed. At most two separators are allowed. The first separator is used to split the file name into two parts, and the second separator is used to join the two parts back together.")]
-    separator: Vec<String>,
-    #[arg(short, long, default_value = "", help = "The padding to use")]
+          allow_hyphen_values = true,
+          env = "BATCH_RENAMER_SEPARATOR",
+          help = "The separator to use, e.g. `-` or `.`. At most two comma-separated separators are allowed. The first separator is used to split the file name into two parts, and the second separator is used to join the two parts back together. Write `\\,` for a comma and `\\\\` for a backslash inside a separator, or just `,` to split and join on commas.")]
+    separator: Separators,
+    #[arg(long, value_name = "PATTERN", conflicts_with = "regex",
+          help = "Split the file name into parts wherever the given regex matches instead of on the first separator, e.g. ` [-—] ` for both kinds of dashes. The parts are still joined with the second separator.")]
+    separator_regex: Option<regex::Regex>,
+    #[arg(long, value_enum, value_name = "SIDE",
+          help = "Only split the file name into as many parts as the order has, on the separators nearest to the given end, instead of on every separator, e.g. `Artist` and `Album - Title` from the left")]
+    split_from: Option<SplitFrom>,
+    #[arg(long = "rules", value_name = "EXT=SEP",
+          help = "Use other separators for the files with an extension, e.g. `--rules jpg=_`, written like `--separator`. Can be given several times; files with other extensions use `--separator`.")]
+    rules: Vec<Rule>,
+    #[arg(short,
+          long,
+          num_args = 1..,
+          value_delimiter = ',',
+          default_value = "1,0",
+          env = "BATCH_RENAMER_ORDER",
+          help = "The order to put the parts split on the separator in, e.g. `3,2,0,1`. Files with a different number of parts are skipped. The default swaps two parts.")]
+    order: Vec<usize>,
+    #[arg(long, value_name = "A:B", conflicts_with = "order",
+          help = "Exchange the two parts with the given indices instead of putting them all in an order, e.g. `1:2`, leaving the others where they are. Files need at least as many parts as the later index names.")]
+    swap_indices: Option<SwapIndices>,
+    #[arg(long, default_value_t = false, conflicts_with_all = ["regex", "template", "exec"],
+          help = "Reverse an earlier run given the same separators and order: split on the new separator, join with the old one and put the parts back where they came from")]
+    reverse: bool,
+    #[arg(long, value_name = "N",
+          help = "Also rename files with a different number of parts than the order, as long as they have at least N. The order moves the parts it names that a file has and leaves the rest at the end.")]
+    min_parts: Option<usize>,
+    #[arg(long, value_name = "N",
+          help = "Also rename files with a different number of parts than the order, as long as they have at most N")]
+    max_parts: Option<usize>,
+    #[arg(short, long, default_value = "", env = "BATCH_RENAMER_PADDING", help = "The padding to use")]
     padding: String,
     #[arg(short, long, default_value_t = false, help = "Whether to rename files recursively")]
     recursive: bool,
+    #[arg(long, value_name = "N", requires = "recursive",
+          help = "How many levels of subdirectories to rename files in, 0 meaning only the given directory")]
+    max_depth: Option<usize>,
+    #[arg(long, default_value_t = false, requires = "recursive",
+          help = "Also rename files in symlinked directories, each directory being visited only once")]
+    follow_symlinks: bool,
+    #[arg(long, default_value_t = false,
+          help = "Also rename directories, in place and after the files in them, as if their whole name was the stem")]
+    include_dirs: bool,
+    #[arg(long, value_name = "DIR", help = "Move the renamed files into the given directory")]
+    target_dir: Option<PathBuf>,
+    #[arg(long, value_name = "DIR",
+          help = "Copy each file into the given directory, keeping its name, before renaming it, unless it is a dry run")]
+    backup: Option<PathBuf>,
+    #[arg(long, default_value_t = false,
+          help = "Create the target directory, or the directories of the new names given with `--from-csv` or `--apply-plan`, if they do not exist")]
+    create_dirs: bool,
+    #[arg(long, default_value_t = false,
+          help = "When a file has to be copied to another filesystem, check that the copy matches before deleting the original")]
+    verify: bool,
+    #[arg(long, default_value_t = false,
+          help = "When a file has to be copied to another filesystem, give the copy the modification and access times of the original")]
+    preserve_timestamps: bool,
+    #[arg(long, default_value_t = false, requires = "target_dir",
+          help = "Recreate the subdirectories the files are in below the target directory")]
+    mirror_dirs: bool,
+    #[arg(long, default_value_t = false, requires = "recursive", conflicts_with = "mirror_dirs",
+          help = "Move the renamed files found in subdirectories up into the top directory, or the target directory if one is given")]
+    flatten: bool,
+    #[arg(long, value_enum, default_value_t = SortOrder::Name, env = "BATCH_RENAMER_SORT",
+          help = "The order to rename, and so number, the files in each directory in")]
+    sort: SortOrder,
+    #[arg(long, default_value_t = false,
+          help = "Rename, and so number, the files in the reverse of the sort order, e.g. newest first with `--sort mtime`")]
+    reverse_sort: bool,
+    #[arg(long, value_name = "N",
+          help = "Only rename the first N files, in the sort order, across all the directories, the next ones standing in for files that could not be renamed, or only preview the first N in a dry run")]
+    limit: Option<usize>,
+    #[arg(long, default_value_t = false, overrides_with = "no_skip_hidden",
+          help = "Leave hidden files and directories alone, which is the default")]
+    skip_hidden: bool,
+    #[arg(long, default_value_t = false, overrides_with = "skip_hidden",
+          help = "Also rename hidden files and look into hidden directories")]
+    no_skip_hidden: bool,
+    #[arg(short = 'n', long, default_value_t = false, help = "Print the renames that would happen without touching any file")]
+    dry_run: bool,
+    #[arg(long, default_value_t = false, conflicts_with_all = ["interactive", "format"],
+          help = "Only print how many files would be renamed, without touching any file")]
+    count_only: bool,
+    #[arg(long, default_value_t = false, conflicts_with_all = ["interactive", "format", "count_only"],
+          help = "Only print the old and new path of each rename, or planned rename in a dry run, each followed by a NUL byte, e.g. for `xargs -0`")]
+    print0: bool,
+    #[arg(long, value_name = "LOGFILE", help = "Revert the renames recorded in the given undo log instead of renaming")]
+    undo: Option<String>,
+    #[arg(long, value_name = "JOURNAL", conflicts_with_all = ["undo", "dry_run", "count_only", "interactive", "stdin"],
+          help = "Finish the renames of an interrupted run from the journal it left, `.batch_renamer_journal.jsonl`")]
+    resume: Option<PathBuf>,
+    #[cfg(feature = "watch")]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["undo", "dry_run", "count_only", "interactive", "stdin", "format"],
+          help = "After renaming, keep watching the directories and rename every file created in or moved into them")]
+    watch: bool,
+    #[arg(long, value_enum, default_value_t = ConflictPolicy::Skip, env = "BATCH_RENAMER_ON_CONFLICT",
+          help = "What to do when a rename would replace another file")]
+    on_conflict: ConflictPolicy,
+    #[arg(long, value_name = "SUFFIX", default_value = " ({n})",
+          help = "The suffix `--on-conflict rename` adds before the extension, `{n}` being the first number making the name free")]
+    conflict_suffix: String,
+    #[arg(long, default_value_t = false, conflicts_with = "force",
+          help = "Never replace an existing file, the same as `--on-conflict skip` but winning over any other policy, e.g. from the config or `BATCH_RENAMER_ON_CONFLICT`")]
+    no_clobber: bool,
+    #[arg(long, default_value_t = false, conflicts_with = "on_conflict",
+          help = "Replace existing files, the same as `--on-conflict overwrite`")]
+    force: bool,
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..), env = "BATCH_RENAMER_JOBS",
+          help = "The number of files to rename in parallel, defaults to the number of CPUs")]
+    jobs: Option<u64>,
+    #[arg(long, value_name = "N", default_value_t = 0,
+          help = "Retry a rename that failed because the file was busy or the like up to the given number of times")]
+    retries: u32,
+    #[arg(long, value_name = "MS", default_value_t = 100, help = "How many milliseconds to wait before each retry")]
+    retry_delay: u64,
+    #[arg(long, value_name = "GLOB",
+          help = "Only rename files whose name matches the given glob pattern, can be given multiple times to allow any of several")]
+    include: Vec<glob::Pattern>,
+    #[arg(long, value_name = "GLOB",
+          help = "Leave files whose name matches the given glob pattern alone, can be given multiple times. Files and directories listed in a `.renamerignore`, written like a `.gitignore`, are left alone too in its directory and below.")]
+    exclude: Vec<glob::Pattern>,
+    #[arg(long, value_name = "SIZE", value_parser = filter::parse_size,
+          help = "Only rename files of at least the given size, e.g. `1K` or `500M`")]
+    min_size: Option<u64>,
+    #[arg(long, value_name = "SIZE", value_parser = filter::parse_size,
+          help = "Only rename files of at most the given size, e.g. `1K` or `500M`")]
+    max_size: Option<u64>,
+    #[arg(long, value_name = "WHEN", value_parser = filter::parse_age,
+          help = "Only rename files modified less than the given time ago, or since the given time, e.g. `3h`, `2d`, `2024-01-01` or `last monday`")]
+    newer_than: Option<Duration>,
+    #[arg(long, value_name = "WHEN", value_parser = filter::parse_age,
+          help = "Only rename files modified more than the given time ago, or before the given time, e.g. `3h`, `2d`, `2024-01-01` or `last monday`")]
+    older_than: Option<Duration>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["undo", "resume"],
+          help = "Only rename files modified since the run that last wrote the given file, then write when this run started to it, unless it is a dry run, e.g. for a recurring job")]
+    state: Option<PathBuf>,
+    #[arg(short, long, action = clap::ArgAction::Count, help = "Also print the files that were skipped or excluded, and why")]
+    verbose: u8,
+    #[arg(short, long, default_value_t = false, conflicts_with = "verbose", help = "Only print the summary")]
+    quiet: bool,
+    #[arg(long, value_enum, default_value_t = Format::Text,
+          help = "How to print the renames and the summary")]
+    format: Format,
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto,
+          help = "Whether to highlight the part of each name a dry run changes")]
+    color: ColorChoice,
+    #[arg(long, value_name = "FILE", help = "Append a timestamped line for every rename, skip and failure to the given file")]
+    log: Option<PathBuf>,
+    #[arg(long, default_value_t = false, help = "Exit with status 2 when no file was renamed")]
+    error_on_no_match: bool,
+    #[arg(long, value_name = "PATTERN", requires = "replace",
+          help = "Match the file name (without extension) against the given regex instead of splitting it on the separator")]
+    regex: Option<regex::Regex>,
+    #[arg(long, value_name = "TEMPLATE", requires = "regex",
+          help = "The new file name (without extension) with `$1`, `$2` etc. replaced by the regex capture groups")]
+    replace: Option<String>,
+    #[arg(long, value_name = "PATTERN", conflicts_with = "regex",
+          help = "Build the new file name (without extension) from a template instead of swapping parts. Tokens are `{stem}`, `{ext}`, `{part0}`, `{part1}`, ... (the parts split on the separator), `{n}` (the sequence number), `{parent}` (the directory name) and `{date}` (the modification date)")]
+    template: Option<batch_rename::Template>,
+    #[arg(long, value_name = "PATTERN", conflicts_with = "template",
+          help = "Name the files that do not split into the expected parts, or do not match `--regex`, from this template instead of skipping them, with the same tokens as `--template`")]
+    fallback_template: Option<batch_rename::Template>,
+    #[arg(long, value_name = "CMD",
+          help = "Run the shell command on each file, with the stem on stdin and as `$1`, and use what it prints as the new stem. Files the command fails on are skipped.")]
+    exec: Option<String>,
+    #[arg(long, value_name = "SECS", default_value_t = 10, requires = "exec",
+          help = "Kill the `--exec` command and skip the file if it runs longer than this")]
+    exec_timeout: u64,
+    #[cfg(feature = "id3")]
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "regex",
+          help = "Build the new file name from the ID3 tags instead, e.g. `{track} - {artist} - {title}`. Known tags are artist, title, track and album")]
+    from_id3: Option<batch_rename::Template>,
+    #[cfg(feature = "exif")]
+    #[arg(long, value_name = "FORMAT", conflicts_with = "regex",
+          help = "Name photos after their EXIF capture date in the given strftime format instead, e.g. `%Y-%m-%d_%H%M%S`")]
+    from_exif: Option<String>,
+    #[arg(long, value_name = "SET", num_args = 0..=1, default_missing_value = "",
+          help = "Remove any of the given characters from both ends of the new file name, or whitespace if none are given")]
+    strip_chars: Option<String>,
+    #[arg(long, value_name = "TEXT",
+          help = "Text to replace in the new file name, can be given multiple times to replace several texts in order")]
+    find: Vec<String>,
+    #[arg(long, value_name = "TEXT",
+          help = "What to replace the text of the corresponding --find with, defaults to deleting it")]
+    replace_with: Vec<String>,
+    #[arg(long, default_value_t = false,
+          help = "Collapse runs of whitespace in the new file name into a single space and trim it")]
+    squeeze_spaces: bool,
+    #[arg(long, value_name = "CHAR",
+          help = "Replace each of the --separator-chars in the new file name with the given character and collapse runs of it")]
+    normalize_separators: Option<char>,
+    #[arg(long, value_name = "CHARS", default_value = "-_.", requires = "normalize_separators",
+          help = "The characters --normalize-separators replaces")]
+    separator_chars: String,
+    #[arg(long, value_enum, default_value_t = StemCase::None,
+          help = "Change the case of the new file name, leaving the extension as it is")]
+    case: StemCase,
+    #[arg(long, value_name = "TEXT", help = "Text to insert into the new file name at the --at position")]
+    insert: Option<String>,
+    #[arg(long, value_name = "INDEX", default_value_t = 0, requires = "insert", allow_negative_numbers = true,
+          help = "The character position to --insert at, counting from the end if negative")]
+    at: isize,
+    #[arg(long, value_name = "ALGO",
+          help = "Append `_` and a digest of the contents to the new file name: `crc32` or `sha256`, cut to a length with e.g. `sha256:8`")]
+    append_hash: Option<HashSpec>,
+    #[arg(long, default_value = "",
+          help = "Text to put before the new file name. Files that cannot be split are still renamed when given")]
+    prefix: String,
+    #[arg(long, default_value = "",
+          help = "Text to put after the new file name, before the extension. Files that cannot be split are still renamed when given")]
+    suffix: String,
+    #[arg(long, default_value_t = false,
+          help = "Replace the characters Windows does not allow in file names, `<>:\"/\\|?*`, trim trailing dots and spaces, and append `_` to names Windows reserves, like `CON`")]
+    sanitize: bool,
+    #[arg(long, value_name = "TEXT", default_value = "_", requires = "sanitize",
+          help = "What --sanitize replaces each character with")]
+    sanitize_replacement: String,
+    #[arg(long, default_value_t = false,
+          help = "Replace accented letters in the new file name with ASCII ones, e.g. `é` with `e`, dropping the characters that have none")]
+    ascii_fold: bool,
+    #[arg(long, default_value_t = false,
+          help = "Turn the new file name into a web-safe slug, lowercase with dashes and only `a-z` and `0-9`, e.g. `my-song-live`")]
+    slugify: bool,
+    #[arg(long, value_enum, value_name = "FORM",
+          help = "Put the new file name in the given Unicode normalization form, so names compare the same across platforms")]
+    normalize: Option<UnicodeForm>,
+    #[arg(long, default_value_t = false, help = "Insert a sequence number into each new file name, restarting in every directory")]
+    number: bool,
+    #[arg(long, default_value_t = 1, help = "The number given to the first file by --number, --renumber and `{n}`")]
+    number_start: u64,
+    #[arg(long, default_value_t = 2,
+          help = "The minimum number of digits of --number, --renumber and `{n}`, padded with leading zeros")]
+    number_width: usize,
+    #[arg(long, value_enum, default_value_t = NumberPosition::Prefix, requires = "number",
+          help = "Where to insert the number")]
+    number_position: NumberPosition,
+    #[arg(long, value_name = "GROUP",
+          help = "Number the files of each directory separately in groups, by the directory they are in with `subdir`, e.g. for listed files, or by what a regex matches in the file name with `regex:<pattern>`, in its first group if it has one, e.g. `regex:^(.*) - \\d+`")]
+    group_by: Option<GroupBy>,
+    #[arg(long, default_value_t = false, conflicts_with_all = ["number", "regex", "template"],
+          help = "Keep the file names and replace the number each one starts with by a sequence number, restarting in every directory and group and counting in the `--sort` order, e.g. `3 - A`, `7 - B` become `01 - A`, `02 - B`")]
+    renumber: bool,
+    #[arg(long, value_name = "PATTERN", requires = "renumber",
+          help = "Find the number to replace with the given regex, in its first group if it has one, instead of at the start of the file name")]
+    renumber_regex: Option<regex::Regex>,
+    #[arg(long, default_value_t = false, requires = "renumber",
+          help = "Number the files without a number to replace after the others, at the start of their names, instead of skipping them")]
+    unnumbered_last: bool,
+    #[arg(long, value_name = "N", default_value_t = 255,
+          help = "Cut new file names longer than the given number of bytes short, keeping the extension")]
+    max_name_len: usize,
+    #[arg(long, default_value_t = false,
+          help = "Rename the files whose paths are read from stdin, one per line, instead of the ones in the directory, which then only holds the undo log")]
+    stdin: bool,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["stdin", "undo", "resume"],
+          help = "Perform exactly the renames in the given CSV of `old,new` paths instead of working them out, e.g. ones exported with `--export-csv` and edited. The directory then only holds the undo log.")]
+    from_csv: Option<PathBuf>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["stdin", "undo", "resume", "from_csv"],
+          help = "Perform exactly the renames in the given JSON plan, `{\"ops\": [{\"from\": \"...\", \"to\": \"...\"}]}`, with the same conflict policy and undo log as any run. The plan is turned down before any rename if a file to rename does not exist, a new name is a directory or in a missing one without `--create-dirs`, or two renames share a file or a new name. The directory then only holds the undo log.")]
+    apply_plan: Option<PathBuf>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["undo", "resume"],
+          help = "Write the renames of the run, or the ones a dry run would perform, to the given CSV of `old,new` paths")]
+    export_csv: Option<PathBuf>,
+    #[arg(short, long, default_value_t = false, conflicts_with_all = ["format", "stdin"],
+          help = "Ask before each rename: y(es), n(o), a(ll remaining) or q(uit)")]
+    interactive: bool,
+    #[arg(long, value_name = "SHELL", hide = true, help = "Print the completion script for the given shell")]
+    generate_completions: Option<clap_complete::Shell>,
+    #[arg(long, value_name = "FILE", conflicts_with = "no_config",
+          help = "Load default options from the given TOML file instead of `.batch_renamer.toml` in the directory")]
+    config: Option<String>,
+    #[arg(long, default_value_t = false, help = "Do not load `.batch_renamer.toml` from the directory")]
+    no_config: bool,
+    /// Whether the extensions were left at the default, so `--lowercase-ext` takes every extension.
+    #[arg(skip)]
+    default_extensions: bool,
+}
+
+impl Args {
+    /**
+     * Fills in the options from the config that were not given on the command
+     * line or in a `BATCH_RENAMER_*` environment variable.
+     */
+    fn merge(&mut self, config: Config, matches: &ArgMatches) {
+        let given = |id: &str| {
+            matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+        };
+        if config.extensions.is_some() && !given("extensions") {
+            self.default_extensions = false;
+        }
+        macro_rules! merge {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if let Some(value) = config.$field {
+                        if !given(stringify!($field)) {
+                            self.$field = value.into();
+                        }
+                    }
+                )*
+            };
+        }
+        merge!(canonicalize, extensions, include_no_ext, case_sensitive_ext, new_extension, ext_case, lowercase_ext,
+               separator, separator_regex, split_from, rules, order, min_parts, max_parts, padding, recursive,
+               max_depth, follow_symlinks, include_dirs, target_dir, backup, create_dirs, verify, preserve_timestamps,
+               mirror_dirs, flatten, sort, reverse_sort, limit, on_conflict, conflict_suffix, jobs, retries,
+               retry_delay, include, exclude, min_size, max_size, newer_than, older_than, state, regex, replace,
+               template, fallback_template, exec, exec_timeout, strip_chars, find, replace_with, squeeze_spaces,
+               normalize_separators, separator_chars, case, insert, at, append_hash, prefix, suffix, sanitize,
+               sanitize_replacement, ascii_fold, slugify, normalize, number, number_start, number_width,
+               number_position, group_by, renumber, renumber_regex, unnumbered_last, max_name_len);
+        #[cfg(feature = "id3")]
+        merge!(from_id3);
+        #[cfg(feature = "exif")]
+        merge!(from_exif);
+
+        // an order on the command line wins over swapping parts in the config
+        if let Some(swap_indices) = config.swap_indices {
+            if !given("swap_indices") && !given("order") {
+                self.swap_indices = Some(swap_indices);
+            }
+        }
+        if let Some(skip_hidden) = config.skip_hidden {
+            if !given("skip_hidden") && !given("no_skip_hidden") {
+                self.no_skip_hidden = !skip_hidden;
+            }
+        }
+    }
+
+    /**
+     * The config file to load: the given one, or the one in the first directory if it exists.
+     */
+    fn config_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.config {
+            return Some(PathBuf::from(path));
+        }
+        let path = Path::new(self.directory.first().map_or(".", String::as_str)).join(config::CONFIG_NAME);
+        (!self.no_config && path.is_file()).then_some(path)
+    }
+
+    /**
+     * Checks the constraints between options that clap cannot check once the
+     * config is merged in.
+     */
+    fn check(&self) -> Result<(), String> {
+        if self.replace_with.len() > self.find.len() {
+            return Err("every --replace-with needs a --find".to_string());
+        }
+        if self.find.iter().any(String::is_empty) {
+            return Err("--find cannot be empty".to_string());
+        }
+        if self.regex.is_some() != self.replace.is_some() {
+            return Err("--regex and --replace must be given together".to_string());
+        }
+        if self.regex.is_some() && self.template.is_some() {
+            return Err("--template cannot be used with --regex".to_string());
+        }
+        if self.renumber && (self.number || self.regex.is_some() || self.template.is_some()) {
+            return Err("--renumber cannot be used with --number, --regex or --template".to_string());
+        }
+        if self.lowercase_ext && (self.renumber || self.regex.is_some() || self.template.is_some()) {
+            return Err("--lowercase-ext cannot be used with --renumber, --regex or --template".to_string());
+        }
+        if self.create_dirs && self.target_dir.is_none() && self.from_csv.is_none() && self.apply_plan.is_none() {
+            return Err("--create-dirs needs --target-dir, --from-csv or --apply-plan".to_string());
+        }
+        if let (Some(min), Some(max)) = (self.min_parts, self.max_parts) {
+            if min > max {
+                return Err(format!("--min-parts {} is more than --max-parts {}", min, max));
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * The separator to split file names on and the one to join the parts back
+ * together with, given as `old` or `old,new`.
+ */
+#[derive(Debug, Clone)]
+struct Separators {
+    old: String,
+    new: String,
+}
+
+impl FromStr for Separators {
+    type Err = String;
+
+    /**
+     * Splits on unescaped commas. `\,` stands for a comma, `\\` for a backslash,
+     * and `\-` for a dash, which could otherwise be taken for a flag. Any
+     * other backslash is kept as it is. A lone `,` is a comma separator.
+     */
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "," {
+            return Ok(Separators { old: ",".to_string(), new: ",".to_string() });
+        }
+
+        let mut separators = vec![String::new()];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some(',' | '\\' | '-')) => {
+                    separators.last_mut().unwrap().push(chars.next().unwrap());
+                }
+                ',' => separators.push(String::new()),
+                c => separators.last_mut().unwrap().push(c),
+            }
+        }
+
+        match <[String; 2]>::try_from(separators) {
+            Ok([old, new]) => Ok(Separators { old, new }),
+            Err(separators) if separators.len() == 1 => {
+                Ok(Separators { new: separators[0].clone(), old: separators[0].clone() })
+            }
+            Err(_) => Err("at most two separators are allowed".to_string()),
+        }
+    }
+}
+
+/**
+ * The separators for the files with an extension, given as `ext=sep` where
+ * `sep` is written like `--separator`.
+ */
+#[derive(Debug, Clone)]
+struct Rule {
+    extension: String,
+    separators: Separators,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (extension, separators) = text.split_once('=')
+            .ok_or_else(|| format!("`{}` is not an extension and a separator like `jpg=_`", text))?;
+        let extension = extension.trim_start_matches('.').to_string();
+        if extension.is_empty() {
+            return Err(format!("`{}` names no extension", text));
+        }
+        Ok(Rule { extension, separators: separators.parse()? })
+    }
+}
+
+/**
+ * The two parts to exchange, given as `a:b`, leaving the others where they are.
+ */
+#[derive(Debug, Clone, Copy)]
+struct SwapIndices {
+    a: usize,
+    b: usize,
+}
+
+impl SwapIndices {
+    /**
+     * The order that exchanges the two parts, naming every part up to the later one.
+     */
+    fn order(self) -> Vec<usize> {
+        let mut order = (0..=self.a.max(self.b)).collect::<Vec<_>>();
+        order.swap(self.a, self.b);
+        order
+    }
+}
+
+impl FromStr for SwapIndices {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{}` is not two part indices like `0:1`", text);
+        let (a, b) = text.split_once(':').ok_or_else(invalid)?;
+        let a = a.trim().parse().map_err(|_| invalid())?;
+        let b = b.trim().parse().map_err(|_| invalid())?;
+        if a == b {
+            return Err(format!("`{}` swaps a part with itself", text));
+        }
+        Ok(SwapIndices { a, b })
+    }
+}
+
+/**
+ * Performs the planned renames, showing a progress bar with the number of
+ * files renamed so far and how long the rest should take if asked to.
+ */
+#[cfg(feature = "progress")]
+fn perform(plan: RenamePlan, confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+           show_progress: bool) -> Result<RenameReport, RenameError> {
+    if !show_progress {
+        return plan.perform(confirm, &|| {});
+    }
+    let bar = indicatif::ProgressBar::new(plan.renames().count() as u64);
+    bar.set_style(indicatif::ProgressStyle::with_template("{bar:40} {pos}/{len} files, {eta} left")
+        .expect("Invalid progress bar template"));
+    let report = plan.perform(confirm, &|| bar.inc(1));
+    bar.finish_and_clear();
+    report
+}
+
+/**
+ * Performs the planned renames. Progress is only shown with the `progress` feature.
+ */
+#[cfg(not(feature = "progress"))]
+fn perform(plan: RenamePlan, confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+           _show_progress: bool) -> Result<RenameReport, RenameError> {
+    plan.perform(confirm, &|| {})
+}
+
+/**
+ * Asks on the terminal whether to go ahead with a rename, repeating the
+ * question until a valid answer is given. The end of input counts as quit.
+ */
+fn prompt_rename(old_path: &Path, new_path: &Path) -> Confirmation {
+    let stdin = io::stdin();
+    loop {
+        print!("Rename `{}` to `{}`? [y/n/a/q] ", old_path.display(), new_path.display());
+        io::stdout().flush().expect("Could not write prompt");
+
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0 {
+            println!();
+            return Confirmation::Quit;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Confirmation::Yes,
+            "n" | "no" => return Confirmation::No,
+            "a" | "all" => return Confirmation::All,
+            "q" | "quit" => return Confirmation::Quit,
+            _ => println!("Please answer y, n, a or q."),
+        }
+    }
 }
 
 /**
- * Renames the files inside the given directory with the specified extensions,
- * separators, padding, and recursion flag. Returns the number of files renamed.
+ * Drops the trailing separators and `.` components of a directory given on
+ * the command line, e.g. `music/` becomes `music`, so that the paths built from
+ * it look the same however it was typed, and resolves it to an absolute path
+ * without symlinks if asked to.
  *
- * @param directory The directory in which to rename files.
- * @param extensions A list of file extensions to consider for renaming.
- * @param old_sep Separator to split the file name into two parts.
- * @param new_sep Separator to join the two parts back together.
- * @param padding Padding string to use between the separated parts of the new file name.
- * @param recursive Whether to rename files recursively in subdirectories.
+ * @throws RenameError if the directory cannot be resolved or its absolute path is not UTF-8.
+ */
+fn normalize_directory(directory: &str, canonicalize: bool) -> Result<String, RenameError> {
+    let path = if canonicalize {
+        fs::canonicalize(directory)?
+    } else {
+        Path::new(directory).components().collect()
+    };
+    path.into_os_string().into_string().map_err(|path| RenameError::NonUtf8Path(path.into()))
+}
+
+/**
+ * Reads when the last run with this state file started.
  *
- * @return The number of files renamed.
- * @throws std::io::Error if file renaming encounters any issues.
+ * @return The time, or `None` if there was no such run yet, so the file does not exist.
+ * @throws io::Error if the file cannot be read or does not hold a time.
  */
-fn rename_files_swapped(directory: &str, extensions: &[&str],
-                        old_sep: &str, new_sep: &str,
-                        padding: &str, recursive: bool) -> Result<u64> {
-    let paths = fs::read_dir(directory)?;
-    let mut files_renamed = 0;
-
-    for path in paths {
-        let path = path?.path();
-        if path.is_dir() {
-            if recursive {
-                files_renamed += rename_files_swapped(
-                    path.to_str().unwrap(), extensions, old_sep, new_sep, padding, recursive)?;
+fn read_state(path: &Path) -> io::Result<Option<SystemTime>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let time = DateTime::parse_from_rfc3339(text.trim()).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("`{}` does not hold the time of a run: {}", path.display(), e))
+    })?;
+    Ok(Some(time.into()))
+}
+
+/**
+ * The time to write to the state file: when the run started, or just before
+ * the earliest modification of a file that was left over, e.g. failed, declined
+ * or over the limit, so that the next run tries it again.
+ *
+ * @return The time, or `None` to keep the state as it is because it cannot be
+ *         told when a left over file, or a directory that could not be read, was modified.
+ */
+fn state_time(report: &RenameReport, started_at: SystemTime) -> Option<SystemTime> {
+    if report.errors.iter().any(|(_, error)| matches!(error, RenameError::CannotRead(..))) {
+        return None;
+    }
+    let skipped = report.skipped.iter()
+        .filter(|(_, reason)| matches!(reason, SkipReason::OverLimit | SkipReason::TargetExists | SkipReason::Conflict))
+        .map(|(path, _)| path.as_path());
+    let left_over = report.errors.iter().map(|(path, _)| path.as_path())
+        .chain(report.declined.iter().map(PathBuf::as_path))
+        .chain(skipped)
+        .chain(report.skipped_conflicts().into_iter().map(|(path, _)| path));
+
+    let mut time = started_at;
+    for path in left_over {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        time = time.min(modified.checked_sub(Duration::from_nanos(1))?);
+    }
+    Some(time)
+}
+
+/**
+ * Writes the time to the state file, for the next run to only rename the files modified since.
+ */
+fn write_state(path: &Path, time: SystemTime) -> io::Result<()> {
+    fs::write(path, format!("{}\n", DateTime::<Local>::from(time).to_rfc3339()))
+}
+
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.default_extensions = matches.value_source("extensions") == Some(ValueSource::DefaultValue);
+
+    if let Some(shell) = args.generate_completions {
+        clap_complete::generate(shell, &mut Args::command(), env!("CARGO_BIN_NAME"), &mut io::stdout());
+        return;
+    }
+
+    if args.undo.is_none() {
+        if let Some(path) = args.config_path() {
+            match Config::load(&path) {
+                Ok(config) => args.merge(config, &matches),
+                Err(message) => Args::command().error(clap::error::ErrorKind::Io, message).exit(),
             }
-            continue;
         }
+        if let Err(message) = args.check() {
+            Args::command().error(clap::error::ErrorKind::ArgumentConflict, message).exit();
+        }
+    }
 
-        if let Some(extension) = path.extension() {
-            let extension = extension.to_str().expect("Could not get extension");
+    // opened before renaming so a bad path fails early
+    let log = args.log.as_ref().map(|path| {
+        OpenOptions::new().create(true).append(true).open(path).unwrap_or_else(|e| {
+            let message = format!("Could not open log `{}`: {}", path.display(), e);
+            Args::command().error(clap::error::ErrorKind::Io, message).exit()
+        })
+    });
 
-            if extensions.contains(&extension) {
-                let file_stem = path.file_stem().unwrap().to_str().unwrap();
-                let filenames = file_stem
-                    .rsplit(&old_sep)
-                    .map(|s| s.trim())
-                    .collect::<Vec<&str>>();
+    let error_on_no_match = args.error_on_no_match;
+    match run(args, log) {
+        // scripts can tell a run where some files failed from a clean one
+        Ok(summary) if summary.errors > 0 => process::exit(1),
+        Ok(summary) if error_on_no_match && summary.renamed == 0 => process::exit(2),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-                let old_path = path.to_str().unwrap();
-                if filenames.len() != 2 {
-                    println!("Skipping `{}`", old_path);
-                    continue;
-                }
-                
-                let separator = format!("{}{}{}", padding, new_sep, padding);
-                let mut new_file_name = filenames
-                    .join(&separator);
+/**
+ * Renames the files, or undoes earlier renames, as the arguments say and prints
+ * what happened, recording it in the log if one is given.
+ *
+ * @return The totals of the run.
+ * @throws RenameError if the run cannot be started or the log cannot be written.
+ */
+fn run(args: Args, log: Option<File>) -> Result<Summary, RenameError> {
+    let logger = Logger::new(Verbosity::from_flags(args.quiet, args.verbose), args.color.enabled());
+
+    if let Some(log_path) = args.undo {
+        let report = undo::undo_renames(Path::new(&log_path))?;
+        for (from, to) in &report.restored {
+            logger.log(Verbosity::Normal, format_args!("Restoring `{}` to `{}`", from.display(), to.display()));
+        }
+        for reason in &report.skipped {
+            logger.log(Verbosity::Normal, format_args!("Warning: {}, skipping", reason));
+        }
+        println!("Restored {}.", output::count(report.restored.len(), "file"));
+        return Ok(Summary { renamed: report.restored.len(), skipped: report.skipped.len(), ..Summary::default() });
+    }
 
-                let extension = format!(".{}", extension);
-                new_file_name.push_str(&extension);
+    // the listed files are renamed once, keeping the undo log in the first directory
+    let mut directories = args.directory;
+    if args.stdin || args.from_csv.is_some() || args.apply_plan.is_some() {
+        directories.truncate(1);
+    }
+    // check them all up front so a typo does not leave the run half done
+    for directory in &mut directories {
+        check_directory(Path::new(directory))?;
+        *directory = normalize_directory(directory, args.canonicalize)?;
+    }
+    let any_extension = args.lowercase_ext && args.default_extensions;
+    let extensions = if any_extension { vec!["*".to_string()] } else { args.extensions.clone() };
 
-                let new_path = path.parent().unwrap().join(new_file_name);
-                let new_path = new_path.to_str().unwrap();
-                println!("Renaming `{}` to `{}`", old_path, new_path);
+    let mut replace_with = args.replace_with.into_iter();
+    let replacements = args.find.into_iter()
+        .map(|find| (find, replace_with.next().unwrap_or_default()))
+        .collect();
 
-                fs::rename(path, new_path)?;
+    let Separators { old: old_sep, new: new_sep } = args.separator;
+    let mut options = RenameOptions {
+        include_no_ext: args.include_no_ext || args.extensions.iter().any(String::is_empty),
+        extensions: args.extensions.into_iter().filter(|extension| !extension.is_empty()).collect(),
+        any_extension,
+        case_sensitive_ext: args.case_sensitive_ext,
+        new_extension: args.new_extension,
+        ext_case: if args.lowercase_ext { ExtCase::Lower } else { args.ext_case },
+        keep_stem: args.lowercase_ext,
+        old_sep,
+        new_sep,
+        separator_rules: args.rules.into_iter()
+            .map(|Rule { extension, separators }| SeparatorRule { extension, old_sep: separators.old, new_sep: separators.new })
+            .collect(),
+        separator_regex: args.separator_regex,
+        split_from: args.split_from,
+        // the parts after the swapped ones stay put
+        min_parts: args.min_parts.or(args.swap_indices.map(|swap| swap.a.max(swap.b) + 1)),
+        order: args.swap_indices.map_or(args.order, SwapIndices::order),
+        max_parts: args.max_parts,
+        reverse: args.reverse,
+        padding: args.padding,
+        recursive: args.recursive,
+        max_depth: args.max_depth,
+        follow_symlinks: args.follow_symlinks,
+        include_dirs: args.include_dirs,
+        target_dir: args.target_dir,
+        backup_dir: args.backup,
+        create_dirs: args.create_dirs,
+        verify: args.verify,
+        preserve_timestamps: args.preserve_timestamps,
+        mirror_dirs: args.mirror_dirs,
+        flatten: args.flatten,
+        sort: args.sort,
+        reverse_sort: args.reverse_sort,
+        limit: args.limit,
+        skip_hidden: !args.no_skip_hidden,
+        dry_run: args.dry_run || args.count_only,
+        // the flags win over a policy from the config or the environment
+        on_conflict: match (args.no_clobber, args.force) {
+            (true, _) => ConflictPolicy::Skip,
+            (_, true) => ConflictPolicy::Overwrite,
+            _ => args.on_conflict,
+        },
+        conflict_suffix: args.conflict_suffix,
+        jobs: args.jobs.map(|jobs| jobs as usize),
+        retries: args.retries,
+        retry_delay: Duration::from_millis(args.retry_delay),
+        include: args.include,
+        exclude: args.exclude,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        newer_than: args.newer_than,
+        older_than: args.older_than,
+        modified_since: args.state.as_deref().map(read_state).transpose()?.flatten(),
+        regex: args.regex,
+        replace: args.replace.unwrap_or_default(),
+        #[cfg(feature = "id3")]
+        from_id3: args.from_id3,
+        #[cfg(feature = "exif")]
+        from_exif: args.from_exif,
+        template: args.template,
+        fallback_template: args.fallback_template,
+        exec: args.exec,
+        exec_timeout: Duration::from_secs(args.exec_timeout),
+        strip_chars: args.strip_chars,
+        replacements,
+        squeeze_spaces: args.squeeze_spaces,
+        normalize_separators: args.normalize_separators,
+        separator_chars: args.separator_chars,
+        case: args.case,
+        insert: args.insert,
+        insert_at: args.at,
+        append_hash: args.append_hash,
+        prefix: args.prefix,
+        suffix: args.suffix,
+        sanitize: args.sanitize.then_some(args.sanitize_replacement),
+        ascii_fold: args.ascii_fold,
+        slugify: args.slugify,
+        normalize: args.normalize,
+        numbering: Numbering { start: args.number_start, width: args.number_width },
+        number_position: args.number.then_some(args.number_position),
+        group_by: args.group_by,
+        renumber: args.renumber,
+        renumber_regex: args.renumber_regex,
+        renumber_unnumbered: args.unnumbered_last,
+        max_name_len: args.max_name_len,
+        ..RenameOptions::default()
+    };
+    if let Some(journal) = &args.resume {
+        let report = resume_renames(journal, &options)?;
+        match args.format {
+            Format::Text => output::print_text(&report, &logger, false),
+            Format::Json => output::print_json(&report, false),
+        }
+        if let Some(log) = &log {
+            output::write_log(log, &report, false)?;
+        }
+        return Ok(report.summary());
+    }
 
-                files_renamed += 1;
-            }
-        } 
+    let mut confirm = |old_path: &Path, new_path: &Path| {
+        if args.interactive { prompt_rename(old_path, new_path) } else { Confirmation::All }
+    };
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+    let print_text = args.format == Format::Text && !args.count_only && !args.print0;
+    let show_progress = !args.quiet && !args.interactive && io::stdout().is_terminal();
+    let mut total = RenameReport::default();
+    for directory in &directories {
+        // the limit is shared by all the directories
+        options.limit = args.limit.map(|limit| limit.saturating_sub(total.renamed.len()));
+        if let (true, Some(path)) = (print_text, args.from_csv.as_ref().or(args.apply_plan.as_ref())) {
+            logger.log(Verbosity::Normal, format_args!("We are renaming the files listed in {:?} ... ", path));
+        } else if print_text && args.stdin {
+            logger.log(Verbosity::Normal, format_args!("We are renaming files read from stdin with extensions {:?} ... ",
+                extensions));
+        } else if print_text {
+            logger.log(Verbosity::Normal, format_args!("We are renaming files in folder {:?} with extensions {:?} ... ",
+                directory, extensions));
+        }
+
+        let paths = if args.stdin {
+            Some(io::stdin().lock().lines()
+                .filter(|line| line.as_ref().map_or(true, |line| !line.is_empty()))
+                .map(|line| line.map(PathBuf::from))
+                .collect::<io::Result<Vec<_>>>()?)
+        } else {
+            None
+        };
+        let renames = match (&args.from_csv, &args.apply_plan) {
+            (Some(path), _) => Some(mapping::read_renames(path)?),
+            (None, Some(path)) => Some(mapping::read_plan(path, args.create_dirs)?),
+            (None, None) => None,
+        };
+        let plan = match (renames, paths) {
+            (Some(renames), _) => plan_renames(renames, Path::new(directory), &options)?,
+            (None, Some(paths)) => plan_listed_files(paths, Path::new(directory), &options)?,
+            (None, None) => plan(Path::new(directory), &options)?,
+        };
+        // a dry run only needs the plan, unless each rename is to be asked about
+        let report = if options.dry_run && !args.interactive {
+            plan.into_report()
+        } else {
+            perform(plan, &mut confirm, show_progress)?
+        };
+
+        if let Some(log) = &log {
+            output::write_log(log, &report, options.dry_run)?;
+        }
+        if print_text {
+            output::print_text(&report, &logger, options.dry_run);
+        }
+        total.merge(report);
     }
 
-    Ok(files_renamed)
+    if let Some(path) = &args.export_csv {
+        mapping::write_renames(path, &total.renamed)?;
+    }
+    if let (Some(path), false) = (&args.state, options.dry_run) {
+        if let Some(time) = state_time(&total, started_at) {
+            write_state(path, time)?;
+        }
+    }
+    match args.format {
+        Format::Text if args.count_only => println!("{}", total.renamed.len()),
+        Format::Text if args.print0 => output::print_nul_separated(&total.renamed)?,
+        Format::Text if directories.len() > 1 => output::print_total(&total, directories.len(), options.dry_run),
+        Format::Text => {}
+        Format::Json => output::print_json(&total, options.dry_run),
+    }
+    if print_text {
+        output::print_elapsed(&total, started.elapsed(), &logger);
+    }
+    #[cfg(feature = "watch")]
+    if args.watch {
+        logger.log(Verbosity::Normal, format_args!("Watching {:?} for new files ...", directories));
+        let directories: Vec<PathBuf> = directories.iter().map(PathBuf::from).collect();
+        watch::watch(&directories, &options, |report| {
+            if let Some(log) = &log {
+                output::write_log(log, &report, false)?;
+            }
+            output::print_text(&report, &logger, false);
+            Ok(())
+        })?;
+    }
+    Ok(total.summary())
 }
 
-fn main() {
-    let args = Args::parse();
-    println!("We are renaming files in folder {:?} with extensions {:?} ... ",
-        args.directory.as_ref().unwrap(), args.extensions);
-
-    let directory = args.directory.unwrap();
-    let extensions = args.extensions.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
-    let separator = args.separator.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
-    let padding = args.padding.as_str();
-    let recursive = args.recursive;
-
-    // unescape the separator if it is escaped, since dash, which is a special character, could be escaped
-    let old_sep = separator[0].replace("\\", "");
-    let old_sep = old_sep.as_ref();
-    let new_sep = if separator.len() > 1 { separator[1] } else { old_sep };
-    let renamed = rename_files_swapped(&directory, &extensions, old_sep, new_sep, &padding, recursive)
-        .expect("Could not rename files");
-
-    if renamed == 0 {
-        println!("Oops! No files were renamed.");
-    } else {
-        println!("Renamed {} files.", renamed);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn separators(text: &str) -> (String, String) {
+        let separators = text.parse::<Separators>().unwrap();
+        (separators.old, separators.new)
+    }
+
+    #[test]
+    fn one_separator_is_both() {
+        assert_eq!(separators("-"), ("-".to_string(), "-".to_string()));
+        assert_eq!(separators(","), (",".to_string(), ",".to_string()));
+    }
+
+    #[test]
+    fn two_separators_are_old_and_new() {
+        assert_eq!(separators(" - ,_"), (" - ".to_string(), "_".to_string()));
+        assert_eq!(separators("-,"), ("-".to_string(), String::new()));
+        assert!("a,b,c".parse::<Separators>().is_err());
+    }
+
+    #[test]
+    fn backslashes_escape_commas_backslashes_and_dashes() {
+        assert_eq!(separators(r"\,"), (",".to_string(), ",".to_string()));
+        assert_eq!(separators(r"\\"), ("\\".to_string(), "\\".to_string()));
+        assert_eq!(separators(r"\-,\,"), ("-".to_string(), ",".to_string()));
+        assert_eq!(separators(r"\t"), (r"\t".to_string(), r"\t".to_string()));
     }
 }
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+/// The header of a CSV of renames, which is written first and skipped when read.
+const HEADER: [&str; 2] = ["old", "new"];
+
+/**
+ * Reads the `old,new` paths of the renames to perform from a CSV, skipping the
+ * header if there is one.
+ *
+ * @throws io::Error if the file cannot be read or a line does not have two paths.
+ */
+pub fn read_renames(path: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let invalid = |e: csv::Error| io::Error::new(io::ErrorKind::InvalidData, format!("`{}`: {}", path.display(), e));
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_path(path).map_err(invalid)?;
+    let mut renames = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(invalid)?;
+        if i == 0 && record.iter().eq(HEADER) {
+            continue;
+        }
+        match (record.get(0), record.get(1), record.len()) {
+            (Some(old), Some(new), 2) => renames.push((PathBuf::from(old), PathBuf::from(new))),
+            _ => {
+                let message = format!("`{}`: line {} is not an old and a new path", path.display(), i + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+    }
+    Ok(renames)
+}
+
+/**
+ * Writes the `old,new` paths of the renames to a CSV with a header, in a form
+ * `read_renames` reads back.
+ *
+ * @throws io::Error if the file cannot be written.
+ */
+pub fn write_renames(path: &Path, renames: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(HEADER)?;
+    for (old_path, new_path) in renames {
+        writer.write_record([old_path.to_string_lossy(), new_path.to_string_lossy()].iter().map(|path| path.as_bytes()))?;
+    }
+    writer.flush()
+}
+
+/**
+ * A plan of renames made elsewhere, read by `--apply-plan`, e.g.
+ * `{"ops": [{"from": "a - b.mp3", "to": "b - a.mp3"}]}`.
+ */
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+    ops: Vec<PlanOp>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanOp {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/**
+ * Reads the `(old, new)` paths of the renames to perform from a JSON plan and
+ * checks that every file to rename exists, that no new name is a directory or
+ * in one that does not exist, unless `create_dirs`, and that no two renames
+ * share a file or a new name, however their paths are spelled. A bad plan is
+ * so turned down before any file is renamed.
+ *
+ * @throws io::Error if the file cannot be read, is not a plan or the plan is invalid.
+ */
+pub fn read_plan(path: &Path, create_dirs: bool) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("`{}`: {}", path.display(), message));
+    let plan: PlanFile = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string()))?;
+
+    let mut sources = HashSet::new();
+    let mut targets = HashSet::new();
+    for op in &plan.ops {
+        if fs::symlink_metadata(&op.from).is_err() {
+            return Err(invalid(format!("`{}` does not exist", op.from.display())));
+        }
+        if !sources.insert(std::path::absolute(&op.from)?) {
+            return Err(invalid(format!("`{}` is renamed more than once", op.from.display())));
+        }
+        let to = std::path::absolute(&op.to)?;
+        if to.is_dir() {
+            return Err(invalid(format!("`{}` is a directory", op.to.display())));
+        }
+        if !create_dirs && to.parent().is_some_and(|directory| !directory.is_dir()) {
+            return Err(invalid(format!("the directory of `{}` does not exist", op.to.display())));
+        }
+        if !targets.insert(to) {
+            return Err(invalid(format!("more than one file is renamed to `{}`", op.to.display())));
+        }
+    }
+    Ok(plan.ops.into_iter().map(|op| (op.from, op.to)).collect())
+}
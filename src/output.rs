@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::Local;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use batch_rename::{RenameError, RenameReport, SkipReason, Summary};
+
+/**
+ * How the results of a run are printed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// One line per file and a sentence summing up the run.
+    Text,
+    /// A JSON array with one object per file, then a JSON object summing up the run.
+    Json,
+}
+
+/**
+ * Whether to highlight what changes in the names printed by a dry run.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /**
+     * Whether to print in color.
+     */
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && io::stdout().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/**
+ * How much is printed in text format, each level including the ones below it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the summary.
+    Quiet,
+    /// The renames, conflicts and failures too.
+    Normal,
+    /// The skipped and excluded files too, with why.
+    Verbose,
+}
+
+impl Verbosity {
+    /**
+     * The level asked for by `-q` and the number of `-v` flags.
+     */
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, _) => Verbosity::Verbose,
+        }
+    }
+}
+
+/**
+ * Prints messages to stdout when their level is within the verbosity.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Logger {
+    verbosity: Verbosity,
+    color: bool,
+}
+
+impl Logger {
+    pub fn new(verbosity: Verbosity, color: bool) -> Self {
+        Logger { verbosity, color }
+    }
+
+    /**
+     * Prints the message on its own line if `level` is not above the verbosity.
+     */
+    pub fn log(&self, level: Verbosity, message: fmt::Arguments) {
+        if level <= self.verbosity {
+            println!("{}", message);
+        }
+    }
+}
+
+/**
+ * What happened to a file, as printed by `--format json`.
+ */
+#[derive(Debug, Serialize)]
+struct Operation {
+    from: String,
+    to: Option<String>,
+    status: Status,
+    reason: Option<String>,
+    /// The size of a renamed file in bytes.
+    size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Renamed,
+    Unchanged,
+    Skipped,
+    Error,
+}
+
+/**
+ * The totals of a run, as printed by `--format json`.
+ */
+#[derive(Debug, Serialize)]
+struct Totals {
+    #[serde(flatten)]
+    summary: Summary,
+    dry_run: bool,
+}
+
+impl Operation {
+    fn new(from: &Path, to: Option<&Path>, status: Status, reason: Option<String>) -> Self {
+        Operation {
+            from: from.to_string_lossy().into_owned(),
+            to: to.map(|to| to.to_string_lossy().into_owned()),
+            status,
+            reason,
+            size: None,
+        }
+    }
+}
+
+/**
+ * Prints how long the run took and how many files were renamed per second,
+ * unless printing only the summary.
+ */
+pub fn print_elapsed(report: &RenameReport, elapsed: Duration, logger: &Logger) {
+    let per_second = report.renamed.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    logger.log(Verbosity::Normal, format_args!("Took {:.2?}, {:.0} files per second", elapsed, per_second));
+}
+
+/**
+ * Prints what happened to every file, one line each as far as the verbosity
+ * allows, followed by a summary, which is always printed.
+ */
+pub fn print_text(report: &RenameReport, logger: &Logger, dry_run: bool) {
+    if dry_run {
+        print_planned(report, logger);
+    } else {
+        for (old_path, new_path) in &report.renamed {
+            logger.log(Verbosity::Normal,
+                       format_args!("Renaming `{}` to `{}`{}", old_path.display(), new_path.display(), size_note(report, old_path, logger)));
+        }
+    }
+    for (path, reason) in &report.skipped {
+        logger.log(Verbosity::Verbose, format_args!("Skipping `{}`: {}", path.display(), reason));
+    }
+    for path in &report.declined {
+        logger.log(Verbosity::Normal, format_args!("Not renaming `{}`", path.display()));
+    }
+    for conflict in &report.conflicts {
+        logger.log(Verbosity::Normal, format_args!("Conflict: {}", conflict));
+    }
+    for path in &report.excluded {
+        logger.log(Verbosity::Verbose, format_args!("Excluding `{}`", path.display()));
+    }
+    for (path, backup) in &report.backed_up {
+        logger.log(Verbosity::Verbose, format_args!("Backed up `{}` to `{}`", path.display(), backup.display()));
+    }
+
+    for path in &report.truncated {
+        logger.log(Verbosity::Normal, format_args!("Warning: the new name of `{}` was cut short", path.display()));
+    }
+    for (path, dropped) in &report.dropped {
+        logger.log(Verbosity::Normal,
+                   format_args!("Warning: could not fold `{}` to ASCII in the new name of `{}`, dropping it", dropped, path.display()));
+    }
+    for path in &report.clamped {
+        logger.log(Verbosity::Normal,
+                   format_args!("Warning: the new name of `{}` is too short to insert at that position, inserting at the nearest end", path.display()));
+    }
+    for (path, retries) in &report.retried {
+        logger.log(Verbosity::Normal, format_args!("Note: `{}` was renamed after {} retries", path.display(), retries));
+    }
+    for path in &report.copied {
+        logger.log(Verbosity::Normal,
+                   format_args!("Warning: `{}` was copied across filesystems and deleted, not moved atomically", path.display()));
+    }
+
+    for (path, error) in &report.errors {
+        match error {
+            // the error says which directory already
+            RenameError::CannotRead(..) => logger.log(Verbosity::Normal, format_args!("{}", error)),
+            _ => logger.log(Verbosity::Normal, format_args!("Failed to rename `{}`: {}", path.display(), error)),
+        }
+    }
+
+    let skips = skip_counts(report);
+    if !skips.is_empty() {
+        let total = skips.iter().map(|(_, count)| count).sum::<usize>();
+        let groups = skips.iter().map(|(label, count)| format!("{} {}", count, label)).collect::<Vec<_>>();
+        println!("Skipped {}: {}", count(total, "file"), groups.join(", "));
+    }
+
+    let summary = report.summary();
+    let renamed = summary.renamed;
+    let unchanged = match summary.unchanged {
+        0 => String::new(),
+        count => format!(", {} unchanged", count),
+    };
+    let errors = match summary.errors {
+        0 => unchanged,
+        errors => format!("{}, {}", unchanged, count(errors, "error")),
+    };
+    let bytes = format_size(summary.bytes);
+    if renamed == 0 {
+        println!("Oops! No files were renamed{}.", errors);
+    } else if dry_run {
+        println!("Would rename {} ({}){}. Nothing was written.", count(renamed, "file"), bytes, errors);
+    } else {
+        println!("Renamed {} ({}){}.", count(renamed, "file"), bytes, errors);
+    }
+}
+
+/**
+ * Prints the renames of a dry run with the new names lined up in a column, and
+ * the part of each name that changes highlighted if the logger is in color.
+ */
+fn print_planned(report: &RenameReport, logger: &Logger) {
+    let renamed = report.renamed.iter()
+        .map(|(old_path, new_path)| (old_path.display().to_string(), new_path.display().to_string(), size_note(report, old_path, logger)))
+        .collect::<Vec<_>>();
+    let width = renamed.iter().map(|(old_name, _, _)| old_name.chars().count()).max().unwrap_or(0);
+    for (old_name, new_name, size) in &renamed {
+        let padding = " ".repeat(width - old_name.chars().count());
+        let (old_name, new_name) = if logger.color {
+            highlight_change(old_name, new_name)
+        } else {
+            (old_name.clone(), new_name.clone())
+        };
+        logger.log(Verbosity::Normal, format_args!("Would rename {}{} -> {}{}", old_name, padding, new_name, size));
+    }
+}
+
+/**
+ * The size of a renamed file to print after its rename, in verbose mode only.
+ */
+fn size_note(report: &RenameReport, old_path: &Path, logger: &Logger) -> String {
+    match report.sizes.get(old_path) {
+        Some(&size) if logger.verbosity >= Verbosity::Verbose => format!(" ({})", format_size(size)),
+        _ => String::new(),
+    }
+}
+
+/**
+ * The count followed by the noun, made plural unless the count is one, e.g.
+ * `1 file` or `3 files`.
+ */
+pub fn count(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+/**
+ * Formats a size in bytes with the largest binary unit it has at least one of,
+ * e.g. `512 B` or `1.5 MiB`.
+ */
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/**
+ * Colors what is between the start and the end the names have in common, red
+ * in the old name and green in the new one.
+ */
+fn highlight_change(old_name: &str, new_name: &str) -> (String, String) {
+    let prefix = old_name.chars().zip(new_name.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    let (old_rest, new_rest) = (&old_name[prefix..], &new_name[prefix..]);
+    let suffix = old_rest.chars().rev().zip(new_rest.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum::<usize>();
+    let (old_changed, new_changed) = (&old_rest[..old_rest.len() - suffix], &new_rest[..new_rest.len() - suffix]);
+    let common_end = &old_rest[old_changed.len()..];
+    (format!("{}{}{}", &old_name[..prefix], old_changed.red(), common_end),
+     format!("{}{}{}", &new_name[..prefix], new_changed.green(), common_end))
+}
+
+/**
+ * Prints the totals of a run over several directories.
+ */
+pub fn print_total(report: &RenameReport, directories: usize, dry_run: bool) {
+    let summary = report.summary();
+    let verb = if dry_run { "Would rename" } else { "Renamed" };
+    println!("{} {} ({}) in {} in total, {} unchanged, skipped {}, excluded {}, {}.",
+             verb, count(summary.renamed, "file"), format_size(summary.bytes), count(directories, "folder"),
+             summary.unchanged, summary.skipped, summary.excluded, count(summary.errors, "error"));
+}
+
+/**
+ * Counts the files left alone by kind of reason, the most common first.
+ */
+fn skip_counts(report: &RenameReport) -> Vec<(&'static str, usize)> {
+    let mut counts = HashMap::<&str, usize>::new();
+    // unchanged files are counted on their own
+    for (_, reason) in report.skipped.iter().filter(|(_, reason)| *reason != SkipReason::AlreadyNamed) {
+        *counts.entry(reason.label()).or_default() += 1;
+    }
+    let conflicting = report.skipped_conflicts().len();
+    for (label, count) in [("excluded", report.excluded.len()), ("declined", report.declined.len()), ("conflict", conflicting)] {
+        if count > 0 {
+            *counts.entry(label).or_default() += count;
+        }
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a_label, a_count), (b_label, b_count)| b_count.cmp(a_count).then(a_label.cmp(b_label)));
+    counts
+}
+
+/**
+ * Prints what happened to every file as a JSON array, followed by the summary
+ * as a JSON object on its own line.
+ */
+pub fn print_json(report: &RenameReport, dry_run: bool) {
+    let mut operations = Vec::new();
+    for (old_path, new_path) in &report.renamed {
+        let mut operation = Operation::new(old_path, Some(new_path), Status::Renamed, None);
+        operation.size = report.sizes.get(old_path).copied();
+        operations.push(operation);
+    }
+    for (path, reason) in &report.skipped {
+        let status = if *reason == SkipReason::AlreadyNamed { Status::Unchanged } else { Status::Skipped };
+        operations.push(Operation::new(path, None, status, Some(reason.to_string())));
+    }
+    for path in &report.declined {
+        operations.push(Operation::new(path, None, Status::Skipped, Some("declined".to_string())));
+    }
+    for (source, conflict) in report.skipped_conflicts() {
+        let reason = format!("conflict: {}", conflict);
+        operations.push(Operation::new(source, Some(&conflict.target), Status::Skipped, Some(reason)));
+    }
+    for path in &report.excluded {
+        operations.push(Operation::new(path, None, Status::Skipped, Some("excluded".to_string())));
+    }
+    for (path, error) in &report.errors {
+        operations.push(Operation::new(path, None, Status::Error, Some(error.to_string())));
+    }
+
+    println!("{}", serde_json::to_string_pretty(&operations).expect("Could not serialize the operations"));
+    let totals = Totals { summary: report.summary(), dry_run };
+    println!("{}", serde_json::to_string(&totals).expect("Could not serialize the summary"));
+}
+
+/**
+ * Prints the old and new path of every rename, each followed by a NUL byte, so
+ * that names with spaces or newlines survive being piped into another tool.
+ */
+pub fn print_nul_separated(renamed: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    for (old_path, new_path) in renamed {
+        for path in [old_path, new_path] {
+            stdout.write_all(path.as_os_str().as_encoded_bytes())?;
+            stdout.write_all(b"\0")?;
+        }
+    }
+    stdout.flush()
+}
+
+/**
+ * Appends a timestamped line for every rename, skip and failure to the log,
+ * writing through a buffer that is flushed once at the end.
+ */
+pub fn write_log(log: &File, report: &RenameReport, dry_run: bool) -> io::Result<()> {
+    let mut log = BufWriter::new(log);
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+    let result = if dry_run { "would rename" } else { "renamed" };
+    for (old_path, new_path) in &report.renamed {
+        writeln!(log, "{} {} `{}` to `{}`", now, result, old_path.display(), new_path.display())?;
+    }
+    for (path, reason) in &report.skipped {
+        writeln!(log, "{} skipped `{}`: {}", now, path.display(), reason)?;
+    }
+    for path in &report.declined {
+        writeln!(log, "{} skipped `{}`: declined", now, path.display())?;
+    }
+    for (source, conflict) in report.skipped_conflicts() {
+        writeln!(log, "{} skipped `{}` to `{}`: conflict", now, source.display(), conflict.target.display())?;
+    }
+    for path in &report.excluded {
+        writeln!(log, "{} skipped `{}`: excluded", now, path.display())?;
+    }
+    for path in &report.truncated {
+        writeln!(log, "{} truncated the new name of `{}`", now, path.display())?;
+    }
+    for (path, dropped) in &report.dropped {
+        writeln!(log, "{} dropped `{}` from the new name of `{}`", now, dropped, path.display())?;
+    }
+    for path in &report.clamped {
+        writeln!(log, "{} inserted at the nearest end of the new name of `{}`", now, path.display())?;
+    }
+    for (path, backup) in &report.backed_up {
+        writeln!(log, "{} backed up `{}` to `{}`", now, path.display(), backup.display())?;
+    }
+    for (path, retries) in &report.retried {
+        writeln!(log, "{} retried `{}` {} times", now, path.display(), retries)?;
+    }
+    for path in &report.copied {
+        writeln!(log, "{} copied `{}` across filesystems", now, path.display())?;
+    }
+    for (path, error) in &report.errors {
+        writeln!(log, "{} failed `{}`: {}", now, path.display(), error)?;
+    }
+    log.flush()
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
+use exif::{In, Tag, Value};
+
+use crate::{RenameError, Result, SkipReason};
+
+/**
+ * Checks that the date format only uses specifiers chrono understands, since
+ * formatting with an invalid one panics.
+ *
+ * @throws RenameError::InvalidDateFormat if the format is invalid.
+ */
+pub fn check_date_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(RenameError::InvalidDateFormat(format.to_string()));
+    }
+    Ok(())
+}
+
+/**
+ * Builds the new stem of a photo by formatting its EXIF `DateTimeOriginal`
+ * with a strftime-like format, e.g. `%Y-%m-%d_%H%M%S`.
+ *
+ * @return The new stem, or why the file has to be skipped.
+ */
+pub fn stem_from_exif(path: &Path, format: &str) -> std::result::Result<String, SkipReason> {
+    let unreadable = |e: String| SkipReason::UnreadableTags(e);
+    let missing = || SkipReason::MissingTag("DateTimeOriginal".to_string());
+
+    let file = fs::File::open(path).map_err(|e| unreadable(e.to_string()))?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .map_err(|e| unreadable(e.to_string()))?;
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).ok_or_else(missing)?;
+    let Value::Ascii(ref values) = field.value else {
+        return Err(missing());
+    };
+    let taken = values.first()
+        .and_then(|value| exif::DateTime::from_ascii(value).ok())
+        .ok_or_else(missing)?;
+
+    let taken = NaiveDate::from_ymd_opt(taken.year.into(), taken.month.into(), taken.day.into())
+        .and_then(|date| date.and_hms_opt(taken.hour.into(), taken.minute.into(), taken.second.into()))
+        .ok_or_else(missing)?;
+    Ok(taken.format(format).to_string())
+}
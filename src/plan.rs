@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Confirmation, RenameOptions, RenameReport, Result, SkipReason};
+
+/**
+ * A planned rename of a single file.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameOp {
+    /// Where the file is now.
+    pub from: PathBuf,
+    /// Where it is renamed to.
+    pub to: PathBuf,
+    /// Why the file is left alone after all, e.g. because it already has the
+    /// new name, or `None` if it is renamed.
+    pub reason: Option<SkipReason>,
+}
+
+/**
+ * The renames worked out for a directory without touching any file, which can
+ * be inspected, filtered or printed before they are applied.
+ */
+#[derive(Debug)]
+pub struct RenamePlan {
+    /// The planned renames, in the order they were found, followed by the ones left alone.
+    pub ops: Vec<RenameOp>,
+    /// The files left out while planning: the skipped, excluded, declined and conflicting
+    /// files and the ones whose new name was changed to fit.
+    pub report: RenameReport,
+    /// The directory the undo log is kept in.
+    pub(crate) directory: PathBuf,
+    pub(crate) options: RenameOptions,
+}
+
+impl RenamePlan {
+    /**
+     * The renames that will be performed, leaving out the files left alone.
+     */
+    pub fn renames(&self) -> impl Iterator<Item = &RenameOp> {
+        self.ops.iter().filter(|op| op.reason.is_none())
+    }
+
+    /**
+     * Performs the planned renames and records them in the undo log, as
+     * `rename_files_swapped` would. Files that cannot be renamed are left
+     * alone and do not stop the others. The plan is used up, so its renames
+     * cannot be performed twice.
+     *
+     * @return The report of the whole run, including the files left out while planning.
+     * @throws RenameError if the renames cannot be started or the undo log cannot be used.
+     */
+    pub fn apply(self) -> Result<RenameReport> {
+        self.perform(&mut |_, _| Confirmation::All, &|| {})
+    }
+
+    /**
+     * The report a dry run gives, with the planned renames as renamed and the
+     * files left alone as skipped.
+     */
+    pub fn into_report(self) -> RenameReport {
+        let mut report = self.report;
+        for op in self.ops {
+            match op.reason {
+                None => report.renamed.push((op.from, op.to)),
+                Some(reason) => report.skipped.push((op.from, reason)),
+            }
+        }
+        report
+    }
+
+    /**
+     * Like `apply`, but asks `confirm` about every planned rename, in order,
+     * before any file is renamed, and about each one over the limit just before
+     * it stands in for one that was not, and reports what happened to every file.
+     *
+     * @param confirm Called with the old and new path of each planned rename.
+     * @param progress Called once each confirmed rename is done with, from any thread.
+     *
+     * @return The report of the whole run, including the files left out while planning.
+     * @throws RenameError in the same cases as `apply`.
+     */
+    pub fn perform(self, confirm: &mut dyn FnMut(&Path, &Path) -> Confirmation,
+                   progress: &(dyn Fn() + Sync)) -> Result<RenameReport> {
+        let mut report = self.report;
+        let mut ops = Vec::with_capacity(self.ops.len());
+        let mut reserves = Vec::new();
+        for op in self.ops {
+            match op.reason {
+                None => ops.push((op.from, op.to)),
+                Some(SkipReason::OverLimit) => reserves.push((op.from, op.to)),
+                Some(reason) => report.skipped.push((op.from, reason)),
+            }
+        }
+        crate::apply_limited(ops, reserves, &self.directory, &self.options, &mut report, confirm, progress)?;
+        Ok(report)
+    }
+}
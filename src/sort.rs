@@ -0,0 +1,106 @@
+use std::cmp::Ordering;
+use std::fs::DirEntry;
+use serde::Deserialize;
+
+/**
+ * The order the files in a directory are processed, and so numbered, in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// By file name, comparing runs of digits by their value, so `2` comes before `10`.
+    Natural,
+    /// By file name, character by character.
+    #[default]
+    Name,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By size, smallest first.
+    Size,
+}
+
+/**
+ * Sorts the directory entries. Entries whose metadata cannot be read come
+ * first, and ties are broken by file name, so the order is the same on every
+ * run. With `reverse`, the whole order is turned around, ties included.
+ */
+pub fn sort_entries(entries: &mut [DirEntry], order: SortOrder, reverse: bool) {
+    match order {
+        SortOrder::Natural => entries.sort_by(|a, b| {
+            natural_cmp(&a.file_name().to_string_lossy(), &b.file_name().to_string_lossy())
+                .then_with(|| a.file_name().cmp(&b.file_name()))
+        }),
+        SortOrder::Name => entries.sort_by_key(|entry| entry.file_name()),
+        SortOrder::Mtime => entries.sort_by_cached_key(|entry| {
+            (entry.metadata().and_then(|m| m.modified()).ok(), entry.file_name())
+        }),
+        SortOrder::Size => entries.sort_by_cached_key(|entry| (entry.metadata().map(|m| m.len()).ok(), entry.file_name())),
+    }
+    if reverse {
+        entries.reverse();
+    }
+}
+
+/**
+ * Compares two names treating every run of digits as a number, e.g.
+ * `track2` < `track10`. Equal numbers with different leading zeros compare equal.
+ */
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (a_number, a_rest) = split_digits(a);
+            let (b_number, b_rest) = split_digits(b);
+            // without leading zeros, a longer run of digits is a bigger number
+            let ordering = a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(b_number));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (a_rest, b_rest);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+/**
+ * Splits the leading run of digits, without its leading zeros, off the text.
+ */
+fn split_digits(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    (text[..end].trim_start_matches('0'), &text[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(natural_cmp("track2", "track10"), Ordering::Less);
+        assert_eq!(natural_cmp("track10", "track9"), Ordering::Greater);
+        assert_eq!(natural_cmp("2 b", "10 a"), Ordering::Less);
+        assert_eq!(natural_cmp("a1b2", "a1b10"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_do_not_count() {
+        assert_eq!(natural_cmp("track007", "track7"), Ordering::Equal);
+        assert_eq!(natural_cmp("track01", "track2"), Ordering::Less);
+        assert_eq!(natural_cmp("0", "00"), Ordering::Equal);
+    }
+
+    #[test]
+    fn text_compares_by_character() {
+        assert_eq!(natural_cmp("abc", "abd"), Ordering::Less);
+        assert_eq!(natural_cmp("ab", "abc"), Ordering::Less);
+        assert_eq!(natural_cmp("é1", "é1"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "1"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use crate::undo::{self, UndoLog};
+use crate::{conflict, walk, RenameOp, RenameOptions, Result, SkipReason};
+
+/**
+ * The renames of a directory tree, planned one directory at a time as they
+ * are iterated over, so that only the renames of that directory are held at
+ * once. Each directory comes before its subdirectories, and the renames of
+ * directories, if any, come last, deepest first.
+ *
+ * Unlike `plan`, conflicts are only resolved among the renames of the same
+ * directory, so files moved into one target directory from several may still
+ * clash. The files left out before a new name was made for them, e.g. for
+ * having another extension, are not yielded, and the ones that could not be
+ * looked at are yielded as errors.
+ */
+pub struct RenameIter<'a> {
+    collector: walk::Collector<'a>,
+    options: &'a RenameOptions,
+    directory: &'a Path,
+    undo_log: UndoLog,
+    /// The directories still to plan with their depth, the next one last.
+    pending: Vec<(PathBuf, usize)>,
+    /// The renames planned and not yielded yet.
+    ready: VecDeque<Result<RenameOp>>,
+    /// How many more files the limit allows to rename.
+    remaining: usize,
+    /// Whether the renames of the directories were planned already.
+    dirs_planned: bool,
+}
+
+impl<'a> RenameIter<'a> {
+    pub(crate) fn new(directory: &'a Path, options: &'a RenameOptions) -> Result<Self> {
+        let undo_log = UndoLog::load_or_default(&directory.join(undo::UNDO_LOG_NAME))?;
+        let mut collector = walk::Collector::new(options, directory);
+        collector.enter_root()?;
+        Ok(RenameIter {
+            collector,
+            options,
+            directory,
+            undo_log,
+            pending: vec![(directory.to_path_buf(), 0)],
+            ready: VecDeque::new(),
+            remaining: options.limit.unwrap_or(usize::MAX),
+            dirs_planned: false,
+        })
+    }
+
+    /**
+     * Plans the next directory, or the renames of the directories once there is none left.
+     *
+     * @return Whether there was anything left to plan.
+     */
+    fn plan_next(&mut self) -> Result<bool> {
+        let ops = match self.pending.pop() {
+            Some((directory, depth)) => {
+                let subdirs = self.collector.collect_one(&directory, depth)?;
+                let (ops, report) = self.collector.take_ops();
+                self.pending.extend(subdirs.into_iter().rev().map(|subdir| (subdir, depth + 1)));
+                self.ready.extend(report.errors.into_iter().map(|(_, error)| Err(error)));
+                ops
+            }
+            None if !self.dirs_planned => {
+                self.dirs_planned = true;
+                self.collector.take_dir_ops()
+            }
+            None => return Ok(false),
+        };
+
+        let (mut ops, left_alone) = crate::skip_renamed(ops, self.directory, &self.undo_log, self.options.reverse);
+        let conflicts = conflict::resolve_conflicts(&mut ops, self.options)?;
+        for (from, to) in ops {
+            let reason = if self.remaining == 0 {
+                Some(SkipReason::OverLimit)
+            } else {
+                self.remaining -= 1;
+                None
+            };
+            self.ready.push_back(Ok(RenameOp { from, to, reason }));
+        }
+        // unless skipped, the conflicting renames are among the planned ones
+        if self.options.on_conflict == conflict::ConflictPolicy::Skip {
+            let skipped = conflicts.into_iter()
+                .flat_map(|conflict| conflict.sources.into_iter().map(move |from| (from, conflict.target.clone())));
+            self.ready.extend(skipped.map(|(from, to)| Ok(RenameOp { from, to, reason: Some(SkipReason::Conflict) })));
+        }
+        self.ready.extend(left_alone.into_iter().map(Ok));
+        Ok(true)
+    }
+}
+
+impl Iterator for RenameIter<'_> {
+    type Item = Result<RenameOp>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(op) = self.ready.pop_front() {
+                return Some(op);
+            }
+            match self.plan_next() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => {
+                    // nothing can be planned reliably after a directory failed
+                    self.pending.clear();
+                    self.dirs_planned = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
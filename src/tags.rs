@@ -0,0 +1,31 @@
+use std::path::Path;
+use id3::{Tag, TagLike};
+
+use crate::template::Template;
+use crate::SkipReason;
+
+/// The tokens an ID3 template may use.
+pub const ID3_TOKENS: &[&str] = &["artist", "title", "track", "album"];
+
+/**
+ * Builds the new stem of an mp3 file from its ID3 tags. Track numbers are
+ * padded to two digits, and path separators in tag values are replaced so a
+ * tag cannot move the file into another directory.
+ *
+ * @return The new stem, or why the file has to be skipped.
+ */
+pub fn stem_from_id3(path: &Path, template: &Template) -> Result<String, SkipReason> {
+    let tag = Tag::read_from_path(path).map_err(|e| SkipReason::UnreadableTags(e.to_string()))?;
+    template
+        .render(|token| {
+            let value = match token {
+                "artist" => tag.artist()?.to_string(),
+                "title" => tag.title()?.to_string(),
+                "album" => tag.album()?.to_string(),
+                "track" => format!("{:02}", tag.track()?),
+                _ => return None,
+            };
+            Some(value.replace(['/', '\\'], "_"))
+        })
+        .map_err(SkipReason::MissingTag)
+}
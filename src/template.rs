@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use crate::{RenameError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Token(String),
+}
+
+/**
+ * A file name pattern with `{name}` tokens that are filled in per file, e.g.
+ * `{track} - {title}`.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = RenameError;
+
+    fn from_str(pattern: &str) -> Result<Template> {
+        let invalid = || RenameError::InvalidTemplate(pattern.to_string());
+        let mut segments = Vec::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(invalid)? + start;
+            let token = &rest[start + 1..end];
+            if token.is_empty() || token.contains('{') {
+                return Err(invalid());
+            }
+            segments.push(Segment::Token(token.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(invalid());
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(Template { segments })
+    }
+}
+
+impl Template {
+    /**
+     * Checks that the template only uses tokens `is_known` accepts.
+     *
+     * @throws RenameError::UnknownToken for the first token that is not known.
+     */
+    pub fn check_tokens(&self, is_known: impl Fn(&str) -> bool) -> Result<()> {
+        for segment in &self.segments {
+            if let Segment::Token(token) = segment {
+                if !is_known(token) {
+                    return Err(RenameError::UnknownToken(token.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Fills in the tokens with the values returned by `lookup`.
+     *
+     * @return The filled in template, or the first token `lookup` had no value for.
+     */
+    pub fn render(&self, mut lookup: impl FnMut(&str) -> Option<String>) -> std::result::Result<String, String> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Token(token) => rendered.push_str(&lookup(token).ok_or_else(|| token.clone())?),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_literals_and_tokens() {
+        let template = "{track} - {title}!".parse::<Template>().unwrap();
+        assert_eq!(template.segments, vec![
+            Segment::Token("track".to_string()),
+            Segment::Literal(" - ".to_string()),
+            Segment::Token("title".to_string()),
+            Segment::Literal("!".to_string()),
+        ]);
+        assert_eq!("".parse::<Template>().unwrap().segments, Vec::new());
+    }
+
+    #[test]
+    fn rejects_unbalanced_and_empty_tokens() {
+        for pattern in ["{track", "track}", "{}", "{{track}}", "{a}}"] {
+            assert!(matches!(pattern.parse::<Template>(), Err(RenameError::InvalidTemplate(_))), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn renders_the_tokens_it_has_values_for() {
+        let template = "{n}. {stem}".parse::<Template>().unwrap();
+        let lookup = |token: &str| (token == "n").then(|| "01".to_string());
+        assert_eq!(template.render(lookup), Err("stem".to_string()));
+        let lookup = |token: &str| Some(token.to_uppercase());
+        assert_eq!(template.render(lookup), Ok("N. STEM".to_string()));
+    }
+
+    #[test]
+    fn checks_the_tokens() {
+        let template = "{n}-{year}".parse::<Template>().unwrap();
+        assert!(template.check_tokens(|token| token == "n" || token == "year").is_ok());
+        assert!(matches!(template.check_tokens(|token| token == "n"), Err(RenameError::UnknownToken(token)) if token == "year"));
+    }
+}
@@ -0,0 +1,719 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::Deserialize;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::template::Template;
+use crate::{RenameError, RenameOptions, SkipReason};
+
+/// The tokens a `--template` may use, besides `{part0}`, `{part1}`, ...
+pub const TEMPLATE_TOKENS: &[&str] = &["stem", "ext", "n", "parent", "date"];
+
+/**
+ * How to change the case of the new stem.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StemCase {
+    /// Lowercase everything.
+    Lower,
+    /// Uppercase everything.
+    Upper,
+    /// Capitalize the first letter of each word and lowercase the rest.
+    Title,
+    /// Keep the case as it is.
+    #[default]
+    None,
+}
+
+/**
+ * The Unicode normalization form to put the new stem in.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnicodeForm {
+    /// Composed characters, e.g. `é` as one code point, as most systems write them.
+    Nfc,
+    /// Decomposed characters, e.g. `é` as `e` and a combining accent, as macOS reports them.
+    Nfd,
+}
+
+/**
+ * How to change the case of the extension of the renamed files.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtCase {
+    /// Lowercase it, e.g. `.JPG` to `.jpg`.
+    Lower,
+    /// Uppercase it, e.g. `.jpg` to `.JPG`.
+    Upper,
+    /// Keep the case as it is.
+    #[default]
+    Keep,
+}
+
+/**
+ * Where to put the sequence number in the new stem.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberPosition {
+    /// Before the stem.
+    #[default]
+    Prefix,
+    /// After the stem.
+    Suffix,
+}
+
+/**
+ * Which end of the file name to split from when it is only split into as
+ * many parts as the order has.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SplitFrom {
+    /// Split on the first separators, leaving the rest in the last part, e.g.
+    /// `Artist` and `Album - Title`.
+    Left,
+    /// Split on the last separators, leaving the rest in the first part, e.g.
+    /// `Artist - Album` and `Title`.
+    Right,
+}
+
+/**
+ * What the sequence numbers of the renamed files look like.
+ */
+#[derive(Debug, Clone)]
+pub struct Numbering {
+    /// The number given to the first file in each directory.
+    pub start: u64,
+    /// The minimum number of digits, padded with leading zeros.
+    pub width: usize,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Numbering { start: 1, width: 2 }
+    }
+}
+
+impl Numbering {
+    /**
+     * The sequence number of the file after `index` others.
+     *
+     * @return The number, or `None` if it would not fit in a `u64`.
+     */
+    pub fn nth(&self, index: u64) -> Option<u64> {
+        self.start.checked_add(index)
+    }
+
+    /**
+     * Formats the sequence number with leading zeros.
+     */
+    pub fn format(&self, number: u64) -> String {
+        format!("{:0width$}", number, width = self.width)
+    }
+}
+
+/**
+ * How to split the files of a directory into groups that are numbered
+ * separately, given as `subdir` or `regex:<pattern>`.
+ */
+#[derive(Debug, Clone)]
+pub enum GroupBy {
+    /// By the directory each file is in. Only listed files can be in different
+    /// ones, as the files found in each directory are numbered on their own anyway.
+    Subdir,
+    /// By what the regex matches in the stem, in its first group if it has
+    /// one. The files it does not match are a group of their own.
+    Regex(Regex),
+}
+
+impl FromStr for GroupBy {
+    type Err = RenameError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "subdir" {
+            return Ok(GroupBy::Subdir);
+        }
+        text.strip_prefix("regex:")
+            .and_then(|pattern| Regex::new(pattern).ok())
+            .map(GroupBy::Regex)
+            .ok_or_else(|| RenameError::InvalidGroup(text.to_string()))
+    }
+}
+
+impl GroupBy {
+    /**
+     * The group of the file with the given path and stem.
+     */
+    pub fn key(&self, path: &Path, stem: &str) -> String {
+        match self {
+            GroupBy::Subdir => path.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default(),
+            GroupBy::Regex(regex) => regex.captures(stem)
+                .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+                .map(|found| found.as_str().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/**
+ * A file that is about to be renamed.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Source<'a> {
+    /// The current path of the file.
+    pub path: &'a Path,
+    /// The file name without its extension and the leading dot of a dotfile.
+    pub stem: &'a str,
+    /// The extension, without the leading dot.
+    pub extension: &'a str,
+    /// The sequence number the file gets if it is renamed.
+    pub sequence: u64,
+}
+
+/**
+ * The separators to use for the files with an extension instead of the
+ * global ones.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeparatorRule {
+    /// The extension, without the leading dot.
+    pub extension: String,
+    pub old_sep: String,
+    pub new_sep: String,
+}
+
+/**
+ * What `new_stem` could not do quite as asked.
+ */
+#[derive(Debug, Default)]
+pub struct StemWarnings {
+    /// The characters that could not be folded to ASCII and were removed.
+    pub dropped: String,
+    /// Whether the insert position was outside the stem, so the text went at its start or end.
+    pub insert_clamped: bool,
+}
+
+/**
+ * Computes the new stem for a file from its current one, either by filling in
+ * its ID3 tags or EXIF date, filling in the template, applying the regex
+ * replacement, or reordering the parts around the separator, and then runs
+ * the external command on it, strips characters from its ends, replaces text,
+ * cleans up its whitespace and separators, changes its case, inserts text,
+ * adds the prefix and suffix, and folds it to ASCII, makes it safe for
+ * Windows, slugifies it and normalizes its Unicode form if asked to. With a
+ * prefix or suffix, a stem that does not split into the expected parts is kept
+ * as is instead of being skipped. A new stem that is empty or a path is never
+ * used.
+ *
+ * @param source The file being renamed.
+ * @param options The rename options.
+ * @param warnings Gets what could not be done quite as asked.
+ *
+ * @return The new stem, or why the file should be skipped.
+ */
+pub fn new_stem(source: &Source, options: &RenameOptions, warnings: &mut StemWarnings) -> Result<String, SkipReason> {
+    let new_stem = base_stem(source, options)?;
+    let new_stem = match &options.exec {
+        Some(command) => crate::exec::run_command(command, &new_stem, options.exec_timeout)?,
+        None => new_stem,
+    };
+    let new_stem = match &options.strip_chars {
+        Some(chars) => strip_chars(&new_stem, chars),
+        None => new_stem,
+    };
+
+    let new_stem = options.replacements.iter()
+        .fold(new_stem, |stem, (find, replace_with)| stem.replace(find.as_str(), replace_with));
+    let new_stem = if options.squeeze_spaces { squeeze_spaces(&new_stem) } else { new_stem };
+    let new_stem = match options.normalize_separators {
+        Some(separator) => normalize_separators(&new_stem, &options.separator_chars, separator),
+        None => new_stem,
+    };
+    let new_stem = change_case(&new_stem, options.case);
+    let new_stem = match &options.insert {
+        Some(text) => insert_at(&new_stem, text, options.insert_at, &mut warnings.insert_clamped),
+        None => new_stem,
+    };
+    let new_stem = format!("{}{}{}", options.prefix, new_stem, options.suffix);
+    let new_stem = if options.ascii_fold { ascii_fold(&new_stem, &mut warnings.dropped) } else { new_stem };
+    let new_stem = match &options.sanitize {
+        Some(replacement) => sanitize(&new_stem, replacement),
+        None => new_stem,
+    };
+    let new_stem = if options.slugify { slugify(&new_stem) } else { new_stem };
+    let new_stem = match options.normalize {
+        Some(UnicodeForm::Nfc) => new_stem.nfc().collect(),
+        Some(UnicodeForm::Nfd) => new_stem.nfd().collect(),
+        None => new_stem,
+    };
+    // the file must stay in its directory whatever the command or replacements made
+    if new_stem.contains(['/', '\\']) || new_stem == ".." {
+        return Err(SkipReason::PathInName(new_stem));
+    }
+    // nothing but the extension would be left, hiding the file
+    if new_stem.is_empty() {
+        return Err(SkipReason::EmptyName);
+    }
+    Ok(new_stem)
+}
+
+/**
+ * Makes the new stem from the ID3 tags, the EXIF date, the template, the regex,
+ * or the separated parts, whichever is configured.
+ */
+fn base_stem(source: &Source, options: &RenameOptions) -> Result<String, SkipReason> {
+    let file_stem = source.stem;
+    #[cfg(feature = "id3")]
+    if let Some(template) = &options.from_id3 {
+        return crate::tags::stem_from_id3(source.path, template);
+    }
+    #[cfg(feature = "exif")]
+    if let Some(format) = &options.from_exif {
+        return crate::photo::stem_from_exif(source.path, format);
+    }
+
+    if let Some(template) = &options.template {
+        return fill_template(source, template, options);
+    }
+    // with renumbering, only the number changes, once all the files are known
+    if options.keep_stem || options.renumber {
+        return Ok(file_stem.to_string());
+    }
+
+    let result = match &options.regex {
+        Some(regex) => regex.captures(file_stem).ok_or(SkipReason::NoRegexMatch).map(|captures| {
+            let mut new_stem = String::new();
+            captures.expand(&options.replace, &mut new_stem);
+            new_stem
+        }),
+        None => reorder_parts(file_stem, source.extension, options),
+    };
+    match result {
+        Ok(new_stem) => Ok(new_stem),
+        Err(reason) => match &options.fallback_template {
+            // a file the fallback cannot name either is skipped for the first reason
+            Some(template) => fill_template(source, template, options).map_err(|_| reason),
+            // a prefix or suffix alone is reason enough to rename
+            None if !options.prefix.is_empty() || !options.suffix.is_empty() => Ok(file_stem.to_string()),
+            None => Err(reason),
+        },
+    }
+}
+
+/**
+ * Returns whether a `--template` may use the token.
+ */
+pub fn is_template_token(token: &str) -> bool {
+    TEMPLATE_TOKENS.contains(&token)
+        || token.strip_prefix("part").is_some_and(|i| !i.is_empty() && i.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/**
+ * Fills in the template for the file. `{partN}` is the Nth part of the stem
+ * split on the old separator, `{parent}` the name of the directory the file is
+ * in, and `{date}` its modification date.
+ */
+fn fill_template(source: &Source, template: &Template, options: &RenameOptions) -> Result<String, SkipReason> {
+    let (old_sep, _) = separators(source.extension, options);
+    let parts = split_parts(source.stem, old_sep, options);
+
+    template
+        .render(|token| match token {
+            "stem" => Some(source.stem.to_string()),
+            "ext" => Some(source.extension.to_string()),
+            "n" => Some(options.numbering.format(source.sequence)),
+            "parent" => {
+                let parent = fs::canonicalize(source.path.parent()?).ok()?;
+                parent.file_name()?.to_str().map(str::to_string)
+            }
+            "date" => {
+                let modified = fs::metadata(source.path).and_then(|m| m.modified()).ok()?;
+                Some(DateTime::<Local>::from(modified).format("%Y-%m-%d").to_string())
+            }
+            _ => {
+                let i = token.strip_prefix("part")?.parse::<usize>().ok()?;
+                parts.get(i).map(|part| part.to_string())
+            }
+        })
+        .map_err(SkipReason::MissingToken)
+}
+
+/**
+ * Splits the stem into parts on every old separator and joins them back
+ * together in the given order with the padded new separator. The default
+ * order of `1,0` swaps two parts.
+ *
+ * Unless a minimum or maximum number of parts is given, the stem must have as
+ * many parts as the order has. Otherwise the order moves the parts it names
+ * that the stem has, and the parts after the ones it names stay at the end.
+ */
+fn reorder_parts(file_stem: &str, extension: &str, options: &RenameOptions) -> Result<String, SkipReason> {
+    let (old_sep, new_sep) = separators(extension, options);
+    let parts = split_parts(file_stem, old_sep, options);
+
+    if options.min_parts.is_none() && options.max_parts.is_none() {
+        if parts.len() != options.order.len() {
+            return Err(SkipReason::WrongPartCount);
+        }
+    } else if let Some(min) = options.min_parts.filter(|&min| parts.len() < min) {
+        return Err(SkipReason::TooFewParts { parts: parts.len(), min });
+    } else if let Some(max) = options.max_parts.filter(|&max| parts.len() > max) {
+        return Err(SkipReason::TooManyParts { parts: parts.len(), max });
+    }
+
+    // the padding of an earlier run is trimmed off the parts and not put back
+    let padding = if options.reverse { "" } else { options.padding.as_str() };
+    let separator = format!("{}{}{}", padding, new_sep, padding);
+    let order = if options.reverse { invert(&options.order) } else { options.order.clone() };
+    let reordered = order.iter()
+        .filter(|&&i| i < parts.len())
+        .map(|&i| parts[i])
+        .chain(parts.iter().skip(order.len()).copied())
+        .collect::<Vec<&str>>();
+    Ok(reordered.join(&separator))
+}
+
+/**
+ * Splits the stem into trimmed parts on the old separator, or wherever the
+ * separator regex matches if there is one, unless reversing, which splits on
+ * the literal new separator. Only as many parts as the order has are split
+ * off, from the start or the end, if asked to.
+ */
+fn split_parts<'a>(stem: &'a str, old_sep: &str, options: &RenameOptions) -> Vec<&'a str> {
+    let separators = match &options.separator_regex {
+        Some(regex) if !options.reverse => regex.find_iter(stem).map(|found| found.range()).collect::<Vec<_>>(),
+        _ => stem.match_indices(old_sep).map(|(i, sep)| i..i + sep.len()).collect(),
+    };
+    let limit = options.order.len().saturating_sub(1).min(separators.len());
+    let separators = match options.split_from {
+        Some(SplitFrom::Left) => &separators[..limit],
+        Some(SplitFrom::Right) => &separators[separators.len() - limit..],
+        None => &separators[..],
+    };
+
+    let mut parts = Vec::with_capacity(separators.len() + 1);
+    let mut start = 0;
+    for separator in separators {
+        parts.push(stem[start..separator.start].trim());
+        start = separator.end;
+    }
+    parts.push(stem[start..].trim());
+    parts
+}
+
+/**
+ * The separators to split and join files with the extension on: the old and
+ * new ones of the first rule for it, or the global ones if there is none, the
+ * other way around when reversing.
+ */
+pub fn separators<'a>(extension: &str, options: &'a RenameOptions) -> (&'a str, &'a str) {
+    let (old_sep, new_sep) = options.separator_rules.iter()
+        .find(|rule| match options.case_sensitive_ext {
+            true => rule.extension == extension,
+            false => rule.extension.eq_ignore_ascii_case(extension),
+        })
+        .map_or((&options.old_sep, &options.new_sep), |rule| (&rule.old_sep, &rule.new_sep));
+    if options.reverse { (new_sep, old_sep) } else { (old_sep, new_sep) }
+}
+
+/**
+ * The order that puts the parts back where the given one took them from.
+ */
+pub fn invert(order: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; order.len()];
+    for (i, &from) in order.iter().enumerate() {
+        inverse[from] = i;
+    }
+    inverse
+}
+
+/**
+ * Returns whether the order contains every index from `0` to its length exactly once.
+ */
+pub fn is_permutation(order: &[usize]) -> bool {
+    let mut seen = vec![false; order.len()];
+    order.iter().all(|&i| i < order.len() && !std::mem::replace(&mut seen[i], true))
+}
+
+/**
+ * Removes the given characters from both ends of the stem, or whitespace if
+ * none are given.
+ */
+pub fn strip_chars(stem: &str, chars: &str) -> String {
+    if chars.is_empty() {
+        stem.trim().to_string()
+    } else {
+        stem.trim_matches(|c| chars.contains(c)).to_string()
+    }
+}
+
+/**
+ * Collapses every run of whitespace into a single space and trims both ends.
+ */
+pub fn squeeze_spaces(stem: &str) -> String {
+    stem.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/**
+ * Replaces every one of the separator characters in the stem with the given
+ * one and collapses runs of it, e.g. `a_-b.c` becomes `a-b-c`.
+ */
+pub fn normalize_separators(stem: &str, separator_chars: &str, separator: char) -> String {
+    let mut normalized = String::with_capacity(stem.len());
+    for c in stem.chars() {
+        let c = if separator_chars.contains(c) { separator } else { c };
+        if !(c == separator && normalized.ends_with(separator)) {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+/**
+ * Inserts the text before the character at the given position in the stem,
+ * counting from the end if it is negative, so `-1` puts it before the last
+ * character. A position outside the stem puts the text at its start or end and
+ * sets `clamped`.
+ */
+pub fn insert_at(stem: &str, text: &str, at: isize, clamped: &mut bool) -> String {
+    let len = stem.chars().count() as isize;
+    let at = if at < 0 { len + at } else { at };
+    *clamped = !(0..=len).contains(&at);
+    let at = at.clamp(0, len) as usize;
+    let split = stem.char_indices().nth(at).map_or(stem.len(), |(i, _)| i);
+    format!("{}{}{}", &stem[..split], text, &stem[split..])
+}
+
+/**
+ * Changes the case of the stem. Title case treats every run of non-whitespace
+ * characters as a word.
+ */
+pub fn change_case(stem: &str, case: StemCase) -> String {
+    match case {
+        StemCase::Lower => stem.to_lowercase(),
+        StemCase::Upper => stem.to_uppercase(),
+        StemCase::None => stem.to_string(),
+        StemCase::Title => {
+            let mut title = String::with_capacity(stem.len());
+            let mut word_start = true;
+            for c in stem.chars() {
+                if word_start {
+                    title.extend(c.to_uppercase());
+                } else {
+                    title.extend(c.to_lowercase());
+                }
+                word_start = c.is_whitespace();
+            }
+            title
+        }
+    }
+}
+
+/**
+ * Replaces accented Latin letters with their ASCII base letters, e.g. `é` with
+ * `e`, and ligatures and the like with their usual spelling, e.g. `ß` with
+ * `ss`. Any other non-ASCII character is removed and added to `dropped`.
+ */
+pub fn ascii_fold(stem: &str, dropped: &mut String) -> String {
+    let mut folded = String::with_capacity(stem.len());
+    for c in stem.nfd().filter(|&c| !is_combining_mark(c)) {
+        match c {
+            c if c.is_ascii() => folded.push(c),
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'Æ' => folded.push_str("AE"),
+            'œ' => folded.push_str("oe"),
+            'Œ' => folded.push_str("OE"),
+            'ø' => folded.push('o'),
+            'Ø' => folded.push('O'),
+            'đ' | 'ð' => folded.push('d'),
+            'Đ' | 'Ð' => folded.push('D'),
+            'ł' => folded.push('l'),
+            'Ł' => folded.push('L'),
+            'þ' => folded.push_str("th"),
+            'Þ' => folded.push_str("Th"),
+            'ı' => folded.push('i'),
+            c => dropped.push(c),
+        }
+    }
+    folded
+}
+
+/**
+ * Makes the stem web-safe: lowercase, with runs of whitespace, underscores and
+ * dashes turned into a single dash, every other character outside `[a-z0-9]`
+ * removed, and no dash at either end, e.g. `My Song_(Live)` becomes `my-song-live`.
+ */
+pub fn slugify(stem: &str) -> String {
+    let mut slug = String::with_capacity(stem.len());
+    for c in stem.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            slug.push(c);
+        } else if (c.is_whitespace() || c == '_' || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    if slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+/// Names Windows reserves for devices, whatever the extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/**
+ * Replaces the characters Windows does not allow in file names, `<>:"/\|?*`
+ * and control characters, trims the trailing dots and spaces Windows drops,
+ * and appends `_` to names Windows reserves for devices, e.g. `CON` becomes
+ * `CON_`.
+ */
+pub fn sanitize(stem: &str, replacement: &str) -> String {
+    let mut sanitized = String::with_capacity(stem.len());
+    for c in stem.chars() {
+        if matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') || c.is_control() {
+            sanitized.push_str(replacement);
+        } else {
+            sanitized.push(c);
+        }
+    }
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+
+    let device = sanitized.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|name| name.eq_ignore_ascii_case(device)) {
+        sanitized.insert(device.len(), '_');
+    }
+    sanitized
+}
+
+/**
+ * Inserts the zero-padded sequence number into the stem, joined by the padded
+ * separator, e.g. `01 - Title`.
+ *
+ * @param separator The new separator of the file, as resolved by `separators`.
+ */
+pub fn insert_number(stem: &str, number: u64, position: NumberPosition, separator: &str, options: &RenameOptions) -> String {
+    let number = options.numbering.format(number);
+    let separator = format!("{}{}{}", options.padding, separator, options.padding);
+    match position {
+        NumberPosition::Prefix => format!("{}{}{}", number, separator, stem),
+        NumberPosition::Suffix => format!("{}{}{}", stem, separator, number),
+    }
+}
+
+/**
+ * Finds the number `renumber` replaces in the stem: the digits at its start,
+ * or where the regex matches, in its first group if it has one.
+ *
+ * @return The number and where it is in the stem, or `None` if there is none.
+ */
+pub fn find_number(stem: &str, regex: Option<&Regex>) -> Option<(u64, Range<usize>)> {
+    let range = match regex {
+        Some(regex) => {
+            let captures = regex.captures(stem)?;
+            captures.get(1).or_else(|| captures.get(0))?.range()
+        }
+        None => 0..stem.bytes().take_while(u8::is_ascii_digit).count(),
+    };
+    let number = stem[range.clone()].parse().ok()?;
+    Some((number, range))
+}
+
+/**
+ * Shortens the stem on a character boundary so it is at most `max_len` bytes long.
+ *
+ * @return Whether the stem had to be shortened.
+ */
+pub fn truncate_stem(stem: &mut String, max_len: usize) -> bool {
+    if stem.len() <= max_len {
+        return false;
+    }
+    let mut len = max_len;
+    while !stem.is_char_boundary(len) {
+        len -= 1;
+    }
+    stem.truncate(len);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_stem_of(stem: &str, extension: &str, options: &RenameOptions) -> Result<String, SkipReason> {
+        let source = Source { path: Path::new(stem), stem, extension, sequence: 1 };
+        new_stem(&source, options, &mut StemWarnings::default())
+    }
+
+    #[test]
+    fn swaps_the_parts() {
+        assert_eq!(new_stem_of("a - b", "mp3", &RenameOptions::default()), Ok("b-a".to_string()));
+    }
+
+    #[test]
+    fn replacements_cannot_empty_the_stem() {
+        let options = RenameOptions { replacements: vec![("b-a".to_string(), String::new())], ..RenameOptions::default() };
+        assert_eq!(new_stem_of("a - b", "mp3", &options), Err(SkipReason::EmptyName));
+    }
+
+    #[test]
+    fn stripping_cannot_empty_the_stem() {
+        let options = RenameOptions { strip_chars: Some("abxy -".to_string()), ..RenameOptions::default() };
+        assert_eq!(new_stem_of("a - b", "mp3", &options), Err(SkipReason::EmptyName));
+        assert_eq!(new_stem_of("x - y", "mp3", &options), Err(SkipReason::EmptyName));
+    }
+
+    #[test]
+    fn a_regex_cannot_empty_the_stem() {
+        let options = RenameOptions {
+            regex: Some(Regex::new("^(.*)$").unwrap()),
+            replace: String::new(),
+            ..RenameOptions::default()
+        };
+        assert_eq!(new_stem_of("a - b", "mp3", &options), Err(SkipReason::EmptyName));
+    }
+
+    #[test]
+    fn a_template_cannot_empty_the_stem() {
+        let options = RenameOptions { template: Some("{ext}".parse().unwrap()), ..RenameOptions::default() };
+        assert_eq!(new_stem_of("README", "", &options), Err(SkipReason::EmptyName));
+        assert_eq!(new_stem_of("a - b", "mp3", &options), Ok("mp3".to_string()));
+    }
+
+    #[test]
+    fn sanitizing_cannot_empty_the_stem() {
+        let options = RenameOptions { keep_stem: true, sanitize: Some("_".to_string()), ..RenameOptions::default() };
+        assert_eq!(new_stem_of(". .", "mp3", &options), Err(SkipReason::EmptyName));
+        assert_eq!(new_stem_of("con. ", "mp3", &options), Ok("con_".to_string()));
+    }
+
+    #[test]
+    fn slugifying_cannot_empty_the_stem() {
+        let options = RenameOptions { keep_stem: true, slugify: true, ..RenameOptions::default() };
+        assert_eq!(new_stem_of("?!", "mp3", &options), Err(SkipReason::EmptyName));
+        assert_eq!(new_stem_of("Hello World", "mp3", &options), Ok("hello-world".to_string()));
+    }
+
+    #[test]
+    fn the_number_is_joined_with_the_separator_of_the_file() {
+        let rule = SeparatorRule { extension: "txt".to_string(), old_sep: "_".to_string(), new_sep: "_".to_string() };
+        let options = RenameOptions { separator_rules: vec![rule], ..RenameOptions::default() };
+        let (_, separator) = separators("txt", &options);
+        assert_eq!(insert_number("d_c", 2, NumberPosition::Prefix, separator, &options), "02_d_c");
+        let (_, separator) = separators("mp3", &options);
+        assert_eq!(insert_number("b-a", 1, NumberPosition::Suffix, separator, &options), "b-a-01");
+    }
+}
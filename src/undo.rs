@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::error::RenameError;
+use crate::Result;
+
+/// The name of the undo log written into the renamed directory.
+pub const UNDO_LOG_NAME: &str = ".batch_renamer_undo.json";
+
+/**
+ * A single rename that was performed, with both paths stored relative to
+ * the directory containing the log so the log can be replayed from anywhere.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/**
+ * The list of performed renames, in the order they happened.
+ */
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UndoLog {
+    pub renames: Vec<UndoEntry>,
+}
+
+impl UndoLog {
+    /**
+     * Loads the log at the given path, or returns an empty log if it does not exist yet.
+     */
+    pub fn load_or_default(path: &Path) -> Result<UndoLog> {
+        if !path.exists() {
+            return Ok(UndoLog::default());
+        }
+        let file = fs::File::open(path)?;
+        serde_json::from_reader(file).map_err(RenameError::UndoLog)
+    }
+
+    /**
+     * Writes the log to the given path as pretty-printed JSON, or removes the
+     * file if there is nothing left to undo.
+     */
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if self.renames.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self).map_err(RenameError::UndoLog)?;
+        Ok(())
+    }
+
+    /**
+     * Records a rename, storing the paths relative to `base` when possible and
+     * absolute otherwise, e.g. for files moved out of it.
+     */
+    pub fn push(&mut self, base: &Path, from: &Path, to: &Path) {
+        let relative = |path: &Path| match path.strip_prefix(base) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+        };
+        self.renames.push(UndoEntry { from: relative(from), to: relative(to) });
+    }
+
+    /**
+     * Returns whether the file at `path` got its name from a recorded rename,
+     * that is the last recorded rename onto or away from `path` was onto it,
+     * paths in the log being relative to `base`.
+     */
+    pub fn was_renamed(&self, base: &Path, path: &Path) -> bool {
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let path = absolute(path);
+        self.renames.iter().rev()
+            .map(|entry| (absolute(&base.join(&entry.from)), absolute(&base.join(&entry.to))))
+            .find(|(from, to)| *from == path || *to == path)
+            .is_some_and(|(_, to)| to == path)
+    }
+}
+
+/**
+ * What happened while reverting an undo log.
+ */
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    /// The `(renamed, original)` paths of every file that was moved back.
+    pub restored: Vec<(PathBuf, PathBuf)>,
+    /// Why each of the entries left in the log could not be undone.
+    pub skipped: Vec<RenameError>,
+}
+
+/**
+ * Reverts the renames recorded in the given log file, newest first. A rename is
+ * skipped if its original path is occupied again or the renamed file is gone.
+ * Entries that could not be undone are kept in the log.
+ *
+ * @param log_path The path to the undo log.
+ *
+ * @return The restored and skipped files.
+ * @throws RenameError if the log cannot be read or a rename fails.
+ */
+pub fn undo_renames(log_path: &Path) -> Result<UndoReport> {
+    let base = log_path.parent().unwrap_or(Path::new(""));
+    let mut pending = UndoLog::load_or_default(log_path)?.renames;
+    let mut remaining = Vec::new();
+    let mut report = UndoReport::default();
+
+    while let Some(entry) = pending.pop() {
+        let from = base.join(&entry.from);
+        let to = base.join(&entry.to);
+        if from.exists() {
+            report.skipped.push(RenameError::TargetExists(from));
+            remaining.push(entry);
+            continue;
+        }
+        if !to.exists() {
+            report.skipped.push(RenameError::NotFound(to));
+            remaining.push(entry);
+            continue;
+        }
+
+        if let Err(e) = fs::rename(&to, &from) {
+            // keep the log in sync with what is still left to undo
+            pending.push(entry);
+            pending.extend(remaining.into_iter().rev());
+            UndoLog { renames: pending }.save(log_path)?;
+            return Err(e.into());
+        }
+        report.restored.push((to, from));
+    }
+
+    remaining.reverse();
+    UndoLog { renames: remaining }.save(log_path)?;
+    Ok(report)
+}
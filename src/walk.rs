@@ -0,0 +1,538 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use ignore::gitignore::Gitignore;
+use ignore::Match;
+
+use crate::transform::StemWarnings;
+use crate::{filter, hash, sort, transform, ExtCase, NumberPosition, RenameError, RenameOptions, RenameReport, Result,
+            SkipReason, Source};
+
+/// The old path, new stem, extension with any hash before it, new separator, sequence number and group of a file to be
+/// renamed.
+type Planned = (PathBuf, String, String, String, u64, String);
+
+/**
+ * Walks a directory tree and plans the rename of every matching file, without
+ * touching the filesystem.
+ */
+pub(crate) struct Collector<'a> {
+    options: &'a RenameOptions,
+    /// The top directory, which `--mirror-dirs` keeps the layout below.
+    root: &'a Path,
+    ops: Vec<(PathBuf, PathBuf)>,
+    /// The renames of directories, each after the ones of the directories in it.
+    dir_ops: Vec<(PathBuf, PathBuf)>,
+    report: RenameReport,
+    /// Canonical paths of the directories entered so far, to break symlink cycles.
+    visited: HashSet<PathBuf>,
+    /// The ignore file of each directory looked at so far, if it has one.
+    ignores: HashMap<PathBuf, Option<Gitignore>>,
+    /// When the run started, which file ages are measured from.
+    now: SystemTime,
+}
+
+impl<'a> Collector<'a> {
+    pub(crate) fn new(options: &'a RenameOptions, root: &'a Path) -> Self {
+        Collector {
+            options,
+            root,
+            ops: Vec::new(),
+            dir_ops: Vec::new(),
+            report: RenameReport::default(),
+            visited: HashSet::new(),
+            ignores: HashMap::new(),
+            now: SystemTime::now(),
+        }
+    }
+
+    /**
+     * Plans the renames below the top directory. Directories, if renamed at
+     * all, come after the files, deepest first.
+     *
+     * @return The planned `(old, new)` renames and the report of the files that were left out.
+     */
+    pub(crate) fn collect(mut self) -> Result<(Vec<(PathBuf, PathBuf)>, RenameReport)> {
+        self.enter_root()?;
+        self.collect_dir(self.root, 0)?;
+        self.ops.append(&mut self.dir_ops);
+        Ok((self.ops, self.report))
+    }
+
+    /**
+     * Marks the top directory as entered, so a symlink back to it is not followed.
+     */
+    pub(crate) fn enter_root(&mut self) -> Result<()> {
+        if self.options.follow_symlinks {
+            self.visited.insert(fs::canonicalize(self.root)?);
+        }
+        Ok(())
+    }
+
+    /**
+     * Plans the renames of the files in one directory without descending into
+     * its subdirectories, to be taken with `take_ops`. The renames of directories
+     * are kept until `take_dir_ops`. `depth` is how far below the top directory this one is.
+     *
+     * @return The subdirectories to descend into, in the sort order.
+     */
+    pub(crate) fn collect_one(&mut self, directory: &Path, depth: usize) -> Result<Vec<PathBuf>> {
+        let mut subdirs = Vec::new();
+        self.plan_entries(directory, depth, |_, subdir| {
+            subdirs.push(subdir.to_path_buf());
+            Ok(())
+        })?;
+        Ok(subdirs)
+    }
+
+    /**
+     * The renames of files planned so far and the report of the files that were left out.
+     */
+    pub(crate) fn take_ops(&mut self) -> (Vec<(PathBuf, PathBuf)>, RenameReport) {
+        (mem::take(&mut self.ops), mem::take(&mut self.report))
+    }
+
+    /**
+     * The renames of the directories planned so far, deepest first.
+     */
+    pub(crate) fn take_dir_ops(&mut self) -> Vec<(PathBuf, PathBuf)> {
+        let mut dir_ops = mem::take(&mut self.dir_ops);
+        dir_ops.sort_by_key(|(path, _)| Reverse(path.components().count()));
+        dir_ops
+    }
+
+    /**
+     * Plans the renames in the directory and, depth first, the ones below it.
+     */
+    fn collect_dir(&mut self, directory: &Path, depth: usize) -> Result<()> {
+        self.plan_entries(directory, depth, |collector, subdir| collector.collect_dir(subdir, depth + 1))
+    }
+
+    /**
+     * Plans the renames of the entries of the directory, then hands each
+     * subdirectory to descend into to `descend`, in order.
+     * Entries are visited in the sort order, file name by default, so runs are
+     * reproducible and sequence numbers, which restart in every directory, are deterministic.
+     * `depth` is how far below the top directory this one is. A subdirectory
+     * that cannot be listed is reported as an error and left out, but the top
+     * directory fails the run.
+     */
+    fn plan_entries(&mut self, directory: &Path, depth: usize,
+                    mut descend: impl FnMut(&mut Self, &Path) -> Result<()>) -> Result<()> {
+        let options = self.options;
+        let mut entries = match fs::read_dir(directory).and_then(|entries| entries.collect::<io::Result<Vec<_>>>()) {
+            Ok(entries) => entries,
+            Err(e) if depth == 0 => return Err(RenameError::CannotRead(directory.to_path_buf(), e)),
+            Err(e) => {
+                self.report.errors.push((directory.to_path_buf(), RenameError::CannotRead(directory.to_path_buf(), e)));
+                return Ok(());
+            }
+        };
+        sort::sort_entries(&mut entries, options.sort, options.reverse_sort);
+        let mut planned = Vec::new();
+        let mut groups = HashMap::new();
+        let mut subdirs = Vec::new();
+
+        for entry in entries {
+            let path = entry.path();
+            if filter::is_own_file(&path) {
+                continue;
+            }
+            if options.skip_hidden && filter::is_hidden(&path) {
+                self.report.skipped.push((path, SkipReason::Hidden));
+                continue;
+            }
+
+            if path.is_dir() {
+                if self.is_ignored(&path, true) {
+                    if options.include_dirs {
+                        self.report.excluded.push(path);
+                    }
+                    continue;
+                }
+                let enter = options.recursive && options.max_depth.is_none_or(|max_depth| depth < max_depth)
+                    && self.should_descend(&path)?;
+                subdirs.push((path, enter));
+                continue;
+            }
+
+            if !self.plan_file(&path, &mut planned, &mut groups) {
+                self.report.skipped.push((path, SkipReason::WrongExtension));
+            }
+        }
+
+        // the files of a directory come before the ones below it
+        self.finish(planned);
+        let mut dirs = 0;
+        for (subdir, enter) in subdirs {
+            if enter {
+                descend(self, &subdir)?;
+            }
+            if options.include_dirs {
+                self.plan_dir(&subdir, &mut dirs);
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Plans the rename of each of the given files in order. Unlike the files
+     * found in a directory, files that do not exist, are not files or do not
+     * have a matching extension are reported as skipped.
+     *
+     * @return The planned `(old, new)` renames and the report of the files that were left out.
+     */
+    pub(crate) fn collect_files(mut self, paths: impl IntoIterator<Item = PathBuf>) -> (Vec<(PathBuf, PathBuf)>, RenameReport) {
+        let mut planned = Vec::new();
+        let mut groups = HashMap::new();
+        for path in paths {
+            let reason = match fs::metadata(&path) {
+                Err(_) => SkipReason::NotFound,
+                Ok(metadata) if !metadata.is_file() => SkipReason::NotAFile,
+                Ok(_) if self.plan_file(&path, &mut planned, &mut groups) => continue,
+                Ok(_) => SkipReason::WrongExtension,
+            };
+            self.report.skipped.push((path, reason));
+        }
+        self.finish(planned);
+        (self.ops, self.report)
+    }
+
+    /**
+     * Computes the new stem and extension of a file and adds it to `planned`,
+     * unless it is not included, is excluded or no new name can be made for it.
+     * `groups` counts the files planned so far in each group, to number them by.
+     *
+     * @return Whether the file has one of the extensions.
+     */
+    fn plan_file(&mut self, path: &Path, planned: &mut Vec<Planned>, groups: &mut HashMap<String, u64>) -> bool {
+        let options = self.options;
+        // a lossy name still ends with the right extension, but cannot be renamed
+        let file_name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let split = if options.any_extension {
+            filter::split_last_extension(&file_name)
+        } else {
+            filter::split_extension(&file_name, &options.extensions, options.case_sensitive_ext)
+        };
+        let Some((file_stem, extension)) =
+            split.or_else(|| (options.include_no_ext && path.extension().is_none()).then_some((&*file_name, ""))) else {
+            return false;
+        };
+        if let Cow::Owned(_) = file_name {
+            self.report.errors.push((path.to_path_buf(), RenameError::NonUtf8Path(path.to_path_buf())));
+            return true;
+        }
+        let size = match fs::metadata(path).and_then(|metadata| Ok((metadata.len(), self.check_metadata(&metadata)?))) {
+            Ok((size, None)) => size,
+            Ok((_, Some(reason))) => {
+                self.report.skipped.push((path.to_path_buf(), reason));
+                return true;
+            }
+            Err(e) => {
+                self.report.errors.push((path.to_path_buf(), e.into()));
+                return true;
+            }
+        };
+
+        if !options.include.is_empty() && !filter::matches_any(&file_name, &options.include) {
+            self.report.skipped.push((path.to_path_buf(), SkipReason::NotIncluded));
+            return true;
+        }
+        if filter::matches_any(&file_name, &options.exclude) || self.is_ignored(path, false) {
+            self.report.excluded.push(path.to_path_buf());
+            return true;
+        }
+
+        // the hash goes after the stem, where shortening it cannot cut it off
+        let hash = match &options.append_hash {
+            Some(spec) => match hash::digest(path, spec) {
+                Ok(digest) => format!("_{}", digest),
+                Err(e) => {
+                    self.report.errors.push((path.to_path_buf(), e.into()));
+                    return true;
+                }
+            },
+            None => String::new(),
+        };
+        if !hash.is_empty() && file_stem.ends_with(&hash) {
+            self.report.skipped.push((path.to_path_buf(), SkipReason::AlreadyNamed));
+            return true;
+        }
+
+        let (dot, file_stem) = filter::split_leading_dot(file_stem);
+        let group = options.group_by.as_ref().map(|group_by| group_by.key(path, file_stem)).unwrap_or_default();
+        let Some(sequence) = options.numbering.nth(groups.get(&group).copied().unwrap_or_default()) else {
+            self.report.skipped.push((path.to_path_buf(), SkipReason::NumberTooLarge));
+            return true;
+        };
+        let source = Source { path, stem: file_stem, extension, sequence };
+        let mut warnings = StemWarnings::default();
+        let new_stem = match transform::new_stem(&source, options, &mut warnings) {
+            Ok(new_stem) => format!("{}{}", dot, new_stem),
+            Err(reason) => {
+                self.report.skipped.push((path.to_path_buf(), reason));
+                return true;
+            }
+        };
+        self.record_warnings(path, warnings);
+
+        let (_, separator) = transform::separators(extension, options);
+        let extension = options.new_extension.as_deref()
+            .map_or(extension, |new_extension| new_extension.trim_start_matches('.'));
+        let extension = match options.ext_case {
+            // extensionless files stay that way, and an empty new extension drops the dot too
+            _ if extension.is_empty() => hash,
+            ExtCase::Lower => format!("{}.{}", hash, extension.to_lowercase()),
+            ExtCase::Upper => format!("{}.{}", hash, extension.to_uppercase()),
+            ExtCase::Keep => format!("{}.{}", hash, extension),
+        };
+        *groups.entry(group.clone()).or_default() += 1;
+        self.report.sizes.insert(path.to_path_buf(), size);
+        planned.push((path.to_path_buf(), new_stem, extension, separator.to_string(), sequence, group));
+        true
+    }
+
+    /**
+     * Plans the rename of a directory in place, its name being transformed
+     * like the stem of a file, unless it is excluded. `count` counts the
+     * directories planned so far in the same directory, to number them by.
+     */
+    fn plan_dir(&mut self, path: &Path, count: &mut u64) {
+        let options = self.options;
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        if let Cow::Owned(_) = name {
+            self.report.errors.push((path.to_path_buf(), RenameError::NonUtf8Path(path.to_path_buf())));
+            return;
+        }
+        if !options.include.is_empty() && !filter::matches_any(&name, &options.include) {
+            self.report.skipped.push((path.to_path_buf(), SkipReason::NotIncluded));
+            return;
+        }
+        if filter::matches_any(&name, &options.exclude) {
+            self.report.excluded.push(path.to_path_buf());
+            return;
+        }
+
+        let Some(sequence) = options.numbering.nth(*count) else {
+            self.report.skipped.push((path.to_path_buf(), SkipReason::NumberTooLarge));
+            return;
+        };
+        let (dot, name) = filter::split_leading_dot(&name);
+        let source = Source { path, stem: name, extension: "", sequence };
+        let mut warnings = StemWarnings::default();
+        let mut new_name = match transform::new_stem(&source, options, &mut warnings) {
+            Ok(new_name) => format!("{}{}", dot, new_name),
+            Err(reason) => {
+                self.report.skipped.push((path.to_path_buf(), reason));
+                return;
+            }
+        };
+        self.record_warnings(path, warnings);
+        if transform::truncate_stem(&mut new_name, options.max_name_len) {
+            if matches!(new_name.as_str(), "" | ".") {
+                self.report.skipped.push((path.to_path_buf(), SkipReason::EmptyName));
+                return;
+            }
+            self.report.truncated.push(path.to_path_buf());
+        }
+        if let Ok(metadata) = fs::metadata(path) {
+            self.report.sizes.insert(path.to_path_buf(), metadata.len());
+        }
+        *count += 1;
+        self.dir_ops.push((path.to_path_buf(), path.with_file_name(new_name)));
+    }
+
+    /**
+     * Adds what the new stem of the file could not quite be made as asked to the report.
+     */
+    fn record_warnings(&mut self, path: &Path, warnings: StemWarnings) {
+        if !warnings.dropped.is_empty() {
+            self.report.dropped.push((path.to_path_buf(), warnings.dropped));
+        }
+        if warnings.insert_clamped {
+            self.report.clamped.push(path.to_path_buf());
+        }
+    }
+
+    /**
+     * Checks the size of the file and when it was last modified against the
+     * allowed ranges, if any.
+     *
+     * @return Why the file is skipped, or `None` if it is in range.
+     */
+    fn check_metadata(&self, metadata: &fs::Metadata) -> io::Result<Option<SkipReason>> {
+        let options = self.options;
+        if options.min_size.is_some_and(|min_size| metadata.len() < min_size) {
+            return Ok(Some(SkipReason::TooSmall));
+        }
+        if options.max_size.is_some_and(|max_size| metadata.len() > max_size) {
+            return Ok(Some(SkipReason::TooLarge));
+        }
+
+        if options.newer_than.is_none() && options.older_than.is_none() && options.modified_since.is_none() {
+            return Ok(None);
+        }
+        let modified = metadata.modified()?;
+        if options.modified_since.is_some_and(|since| modified <= since) {
+            return Ok(Some(SkipReason::NotModifiedSince));
+        }
+        // files modified in the future are as new as can be
+        let age = self.now.duration_since(modified).unwrap_or_default();
+        if self.options.newer_than.is_some_and(|newer_than| age > newer_than) {
+            return Ok(Some(SkipReason::ModifiedTooLongAgo));
+        }
+        if self.options.older_than.is_some_and(|older_than| age < older_than) {
+            return Ok(Some(SkipReason::ModifiedTooRecently));
+        }
+        Ok(None)
+    }
+
+    /**
+     * Numbers, disambiguates and shortens the planned new names and turns them
+     * into renames, into the target directory if there is one.
+     */
+    fn finish(&mut self, mut planned: Vec<Planned>) {
+        let options = self.options;
+        if options.renumber {
+            planned = self.renumber(planned);
+        }
+        if let Some(position) = options.number_position {
+            for (_, new_stem, _, separator, sequence, _) in planned.iter_mut() {
+                *new_stem = transform::insert_number(new_stem, *sequence, position, separator, options);
+            }
+        }
+
+        #[cfg(feature = "exif")]
+        if options.from_exif.is_some() {
+            disambiguate(&mut planned);
+        }
+
+        for (path, mut new_file_name, extension, _, _, _) in planned {
+            if transform::truncate_stem(&mut new_file_name, options.max_name_len.saturating_sub(extension.len())) {
+                // not even the dot of a dotfile is a name
+                if matches!(new_file_name.as_str(), "" | ".") {
+                    self.report.skipped.push((path, SkipReason::EmptyName));
+                    continue;
+                }
+                self.report.truncated.push(path.clone());
+            }
+            new_file_name.push_str(&extension);
+            let new_path = self.new_directory(&path).join(new_file_name);
+            self.ops.push((path, new_path));
+        }
+    }
+
+    /**
+     * Replaces the number in each planned stem with the sequence number,
+     * counting in the sort order of the files, so that gaps close up and every
+     * number gets the same width. Each group is counted on its own. Files
+     * without a number are numbered after the others in their group if asked
+     * to, and skipped otherwise.
+     */
+    fn renumber(&mut self, planned: Vec<Planned>) -> Vec<Planned> {
+        let options = self.options;
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        let mut renumbered = Vec::with_capacity(planned.len());
+        let mut unnumbered = Vec::new();
+        for (path, mut new_stem, extension, separator, _, group) in planned {
+            match transform::find_number(&new_stem, options.renumber_regex.as_ref()) {
+                Some((_, range)) => {
+                    let count = counts.entry(group.clone()).or_default();
+                    let Some(number) = options.numbering.nth(*count) else {
+                        self.report.skipped.push((path, SkipReason::NumberTooLarge));
+                        continue;
+                    };
+                    *count += 1;
+                    new_stem.replace_range(range, &options.numbering.format(number));
+                    renumbered.push((path, new_stem, extension, separator, number, group));
+                }
+                None if options.renumber_unnumbered => unnumbered.push((path, new_stem, extension, separator, group)),
+                None => self.report.skipped.push((path, SkipReason::NoNumber)),
+            }
+        }
+
+        for (path, new_stem, extension, separator, group) in unnumbered {
+            let count = counts.entry(group.clone()).or_default();
+            let Some(number) = options.numbering.nth(*count) else {
+                self.report.skipped.push((path, SkipReason::NumberTooLarge));
+                continue;
+            };
+            *count += 1;
+            let new_stem = transform::insert_number(&new_stem, number, NumberPosition::Prefix, &separator, options);
+            renumbered.push((path, new_stem, extension, separator, number, group));
+        }
+        renumbered
+    }
+
+    /**
+     * The directory a file is moved to: its own, the top directory when
+     * flattening, or the target directory, below which its subdirectory in the
+     * top directory is recreated when mirroring.
+     */
+    fn new_directory(&self, path: &Path) -> PathBuf {
+        let parent = path.parent().unwrap();
+        match &self.options.target_dir {
+            Some(target_dir) if self.options.mirror_dirs => {
+                target_dir.join(parent.strip_prefix(self.root).unwrap_or(Path::new("")))
+            }
+            Some(target_dir) => target_dir.clone(),
+            None if self.options.flatten => self.root.to_path_buf(),
+            None => parent.to_path_buf(),
+        }
+    }
+
+    /**
+     * Returns whether an ignore file in the directory of the entry, or in one
+     * above it up to the top directory, ignores it. The deepest file with a
+     * matching pattern decides, so a `!pattern` can bring back what a file further up ignores.
+     */
+    fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        for directory in path.ancestors().skip(1).take_while(|directory| directory.starts_with(self.root)) {
+            let ignore = self.ignores.entry(directory.to_path_buf()).or_insert_with(|| {
+                let ignore_path = directory.join(filter::IGNORE_FILE_NAME);
+                // the patterns that could be read still apply if others could not
+                ignore_path.is_file().then(|| Gitignore::new(ignore_path).0)
+            });
+            match ignore.as_ref().map_or(Match::None, |ignore| ignore.matched(path, is_dir)) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /**
+     * Symlinked directories are only entered when following symlinks, and
+     * then only the first time their target is reached.
+     */
+    fn should_descend(&mut self, directory: &Path) -> Result<bool> {
+        if !self.options.follow_symlinks {
+            return Ok(!fs::symlink_metadata(directory)?.file_type().is_symlink());
+        }
+        Ok(self.visited.insert(fs::canonicalize(directory)?))
+    }
+}
+
+/**
+ * Appends `_2`, `_3`, ... to the new stems that would otherwise repeat an earlier
+ * file name, e.g. for photos taken in the same second.
+ */
+#[cfg(feature = "exif")]
+fn disambiguate(planned: &mut [Planned]) {
+    let mut taken = HashSet::new();
+    for (_, new_stem, extension, _, _, _) in planned.iter_mut() {
+        let mut candidate = new_stem.clone();
+        let mut n = 1;
+        while !taken.insert(format!("{}{}", candidate, extension)) {
+            n += 1;
+            candidate = format!("{}_{}", new_stem, n);
+        }
+        *new_stem = candidate;
+    }
+}
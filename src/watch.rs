@@ -0,0 +1,85 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+
+use batch_rename::{filter, plan_listed_files, Confirmation, RenameError, RenameOptions, RenameReport};
+
+/// How long the directories have to be quiet before the files that appeared are renamed.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/**
+ * Watches the directories and renames every file created in or moved into
+ * them, in batches once no event came for a moment, so that a file being
+ * copied in is only renamed once it is all there. Runs until the process is
+ * stopped.
+ *
+ * @param report Called with the report of every batch of renames.
+ * @throws RenameError if the directories cannot be watched or `report` fails.
+ */
+pub fn watch(directories: &[PathBuf], options: &RenameOptions,
+             mut report: impl FnMut(RenameReport) -> Result<(), RenameError>) -> Result<(), RenameError> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    let mode = if options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    // the events name the files under the resolved directories
+    let mut watched = Vec::with_capacity(directories.len());
+    for directory in directories {
+        let resolved = directory.canonicalize()?;
+        watcher.watch(&resolved, mode).map_err(watch_error)?;
+        watched.push((directory, resolved));
+    }
+
+    // the new names of the last batch, whose own events are still to come
+    let mut just_renamed = HashSet::new();
+    while let Ok(event) = receiver.recv() {
+        let mut appeared = Vec::new();
+        let mut event = event.map_err(watch_error)?;
+        loop {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))) {
+                appeared.append(&mut event.paths);
+            }
+            event = match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => event.map_err(watch_error)?,
+                Err(_) => break,
+            };
+        }
+
+        let mut seen = HashSet::new();
+        appeared.retain(|path| {
+            let hidden = options.skip_hidden && filter::is_hidden(path);
+            path.is_file() && !hidden && !is_own_file(path) && !just_renamed.contains(path) && seen.insert(path.clone())
+        });
+        just_renamed.clear();
+        for (directory, resolved) in &watched {
+            let (paths, rest) = appeared.into_iter().partition(|path| path.starts_with(resolved));
+            appeared = rest;
+            if paths.is_empty() {
+                continue;
+            }
+            let batch = plan_listed_files(paths, directory, options)?
+                .perform(&mut |_, _| Confirmation::All, &|| {})?;
+            just_renamed.extend(batch.renamed.iter().map(|(_, new_path)| new_path.clone()));
+            report(batch)?;
+        }
+    }
+    Ok(())
+}
+
+fn watch_error(error: notify::Error) -> RenameError {
+    match error.kind {
+        notify::ErrorKind::Io(e) => RenameError::Io(e),
+        _ => RenameError::Io(io::Error::other(error)),
+    }
+}
+
+/**
+ * Whether the path is one of the files the renamer keeps in a directory, like
+ * the undo log, which are written to while renaming.
+ */
+fn is_own_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.as_encoded_bytes().starts_with(b".batch_renamer"))
+}